@@ -1,9 +1,17 @@
+use vpn_lib::host::{Host, HostError};
+
 #[tauri::command]
 pub async fn get_geo_info(ip: Option<String>) -> Result<serde_json::Value, String> {
     let token = std::env::var("IPINFO_TOKEN").map_err(|_| "Token not found".to_string())?;
 
     let url = match ip {
-        Some(addr) => format!("https://api.ipinfo.io/lite/{}?token={}", addr, token),
+        Some(addr) => {
+            // ipinfo only takes addresses, so tunnels added by hostname are
+            // looked up by whatever the name currently resolves to.
+            let host: Host = addr.parse().map_err(|e: HostError| e.to_string())?;
+            let addr = host.resolve(0).await.map_err(|e| e.to_string())?.ip();
+            format!("https://api.ipinfo.io/lite/{}?token={}", addr, token)
+        }
         None => format!("https://api.ipinfo.io/lite/me?token={}", token),
     };
 
@@ -1,8 +1,8 @@
-use std::{net::Ipv4Addr, str::FromStr, sync::Mutex};
+use std::sync::Mutex;
 use tauri::{AppHandle, Emitter, State};
 use tokio::time::{sleep, Duration};
 use tokio_util::sync::CancellationToken;
-use vpn_lib::network::ping_endpoint;
+use vpn_lib::{host::Host, network::ping_endpoint};
 
 use crate::TunnelState;
 
@@ -14,7 +14,7 @@ pub async fn start_ping_loop(
     state: State<'_, TunnelState>,
     stop_signal: State<'_, PingHandle>,
 ) -> Result<(), String> {
-    let ip_str = {
+    let host_str = {
         let lock = state
             .active_tunnel
             .lock()
@@ -22,7 +22,9 @@ pub async fn start_ping_loop(
         lock.clone().ok_or("No active tunnel found to ping")?
     };
 
-    let addr = Ipv4Addr::from_str(&ip_str).map_err(|_| "Invalid IP address in tunnel state")?;
+    let host: Host = host_str
+        .parse()
+        .map_err(|_| "Invalid server address in tunnel state")?;
 
     let token = CancellationToken::new();
     let child_token = token.child_token();
@@ -36,13 +38,13 @@ pub async fn start_ping_loop(
                 break;
             }
 
-            if let Some(latency) = ping_endpoint(addr).await {
-                app.emit("ping-result", (addr.to_string(), latency))
+            if let Some(latency) = ping_endpoint(&host).await {
+                app.emit("ping-result", (host.to_string(), latency))
                     .unwrap();
             }
             sleep(Duration::from_secs(2)).await;
         }
-        app.emit("ping-stopped", addr.to_string()).unwrap();
+        app.emit("ping-stopped", host.to_string()).unwrap();
     });
 
     Ok(())
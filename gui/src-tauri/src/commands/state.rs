@@ -4,7 +4,7 @@ use tauri::{AppHandle, Emitter, Manager};
 use tokio::time::sleep;
 
 use crate::{
-    commands::tunnel::{is_tunnel_active, metadata::get_all_tunnels},
+    commands::tunnel::{is_tunnel_active, metadata::get_all_tunnels, tunnel_interface_name},
     TunnelPayload, TunnelState,
 };
 
@@ -20,7 +20,7 @@ pub fn start_monitoring(app: AppHandle) {
             };
 
             if let Some(name) = name_to_check {
-                if !is_tunnel_active(name) {
+                if !is_tunnel_active(tunnel_interface_name(&name)) {
                     missing_strikes += 1;
 
                     if missing_strikes >= 2 {
@@ -64,9 +64,10 @@ pub async fn sync_tunnel_state(app: AppHandle) {
 
     if let Ok(tunnels) = get_all_tunnels(&app) {
         for tunnel in tunnels {
-            if is_tunnel_active(tunnel.name.clone()) {
+            if is_tunnel_active(tunnel_interface_name(&tunnel.host.to_string())) {
                 let name = {
-                    let lock = state.active_tunnel.lock().unwrap();
+                    let mut lock = state.active_tunnel.lock().unwrap();
+                    *lock = Some(tunnel.host.to_string());
                     lock.clone()
                 };
 
@@ -1,9 +1,34 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use ipnet::IpNet;
+use secrecy::SecretString;
+use serde::Deserialize;
 use tauri::{AppHandle};
+use vpn_lib::{
+    host::{Host, HostError},
+    wireguard::{
+        config::{ConfigError, WgConfig},
+        export::{config_qr_png, with_private_key_warning},
+        routes::RouteProfile,
+        server::TunnelMode,
+        settings::{ClientSettings, ServerSettings},
+    },
+};
 
 use crate::{
-    commands::tunnel::{
-        metadata::{get_all_tunnels, remove_metadata_from_store, TunnelMetadata},
-        stop_tunnel,
+    commands::{
+        tunnel::{
+            metadata::{
+                get_all_tunnels, get_metadata_from_store, remove_metadata_from_store,
+                save_metadata_to_store, TunnelMetadata,
+            },
+            client_config, stop_tunnel,
+        },
+        utils::{save_key_securely, save_preshared_key_securely},
     },
     TunnelState,
 };
@@ -22,12 +47,212 @@ pub async fn remove_config(
 ) -> Result<(), String> {
     let is_active = {
         let active_tunnel = tunnel_state.active_tunnel.lock().unwrap();
-        active_tunnel.as_ref() == Some(&config.public_ip.to_string())
+        active_tunnel.as_ref() == Some(&config.host.to_string())
     };
 
     if is_active {
         stop_tunnel(app.clone(), tunnel_state).await?;
     }
 
-    remove_metadata_from_store(&app, config.public_ip.to_string())
+    remove_metadata_from_store(&app, config.host.to_string())
+}
+
+#[tauri::command]
+pub async fn trust_host_key(
+    app: AppHandle,
+    server: Host,
+    host: String,
+    fingerprint: String,
+) -> Result<(), String> {
+    let mut metadata = get_metadata_from_store(&app, server.to_string())?
+        .ok_or_else(|| format!("No metadata found for {}", server))?;
+
+    if host == server.to_string() {
+        metadata.host_key_fingerprint = Some(fingerprint);
+    } else {
+        let jump_host = metadata
+            .jump_hosts
+            .iter_mut()
+            .find(|j| j.host.to_string() == host)
+            .ok_or_else(|| format!("{} is not a jump host of {}", host, server))?;
+
+        jump_host.host_key_fingerprint = Some(fingerprint);
+    }
+
+    save_metadata_to_store(&app, metadata)
+}
+
+#[tauri::command]
+pub async fn save_route_profile(
+    app: AppHandle,
+    host: Host,
+    routes: RouteProfile,
+) -> Result<(), String> {
+    let mut metadata = get_metadata_from_store(&app, host.to_string())?
+        .ok_or_else(|| format!("No metadata found for {}", host))?;
+
+    metadata.routes = routes;
+
+    save_metadata_to_store(&app, metadata)
+}
+
+#[tauri::command]
+pub async fn save_client_settings(
+    app: AppHandle,
+    host: Host,
+    client: ClientSettings,
+) -> Result<(), String> {
+    client.validate().map_err(|e| e.to_string())?;
+
+    let mut metadata = get_metadata_from_store(&app, host.to_string())?
+        .ok_or_else(|| format!("No metadata found for {}", host))?;
+
+    metadata.client = client;
+
+    save_metadata_to_store(&app, metadata)
+}
+
+#[tauri::command]
+pub async fn import_config(
+    app: AppHandle,
+    path: PathBuf,
+    name: Option<String>,
+) -> Result<TunnelMetadata, String> {
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let config: WgConfig = contents.parse().map_err(|e: ConfigError| e.to_string())?;
+
+    let name = name
+        .filter(|name| !name.trim().is_empty())
+        .or_else(|| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "Imported tunnel".to_string());
+    let metadata = imported_metadata(name, &config)?;
+
+    if get_metadata_from_store(&app, metadata.host.to_string())?.is_some() {
+        return Err(format!("A tunnel to {} already exists", metadata.host));
+    }
+
+    let private_key = config
+        .interface
+        .private_key
+        .as_ref()
+        .ok_or("The [Interface] section has no PrivateKey")?;
+    save_key_securely(&app, &metadata.host, &SecretString::new(private_key.as_str().into()))
+        .await?;
+
+    if let Some(preshared_key) = &config.peers[0].preshared_key {
+        save_preshared_key_securely(
+            &app,
+            &metadata.host,
+            &SecretString::new(preshared_key.as_str().into()),
+        )
+        .await?;
+    }
+
+    save_metadata_to_store(&app, metadata.clone())?;
+
+    Ok(metadata)
+}
+
+fn imported_metadata(name: String, config: &WgConfig) -> Result<TunnelMetadata, String> {
+    let [server] = config.peers.as_slice() else {
+        return Err(format!(
+            "Expected exactly one [Peer] section, found {}",
+            config.peers.len()
+        ));
+    };
+
+    let endpoint = server
+        .endpoint
+        .as_deref()
+        .ok_or("The [Peer] section has no Endpoint")?;
+    let (host, port) = endpoint
+        .rsplit_once(':')
+        .ok_or_else(|| format!("Endpoint {} has no port", endpoint))?;
+    let host: Host = host.parse().map_err(|e: HostError| e.to_string())?;
+    let listen_port: u16 = port
+        .parse()
+        .map_err(|_| format!("Endpoint {} has an invalid port", endpoint))?;
+
+    let address = &config.interface.address;
+    let subnet = address
+        .iter()
+        .find_map(|net| match net {
+            IpNet::V4(net) => Some(*net),
+            IpNet::V6(_) => None,
+        })
+        .ok_or("The [Interface] section has no IPv4 Address")?;
+    let subnet_v6 = address.iter().find_map(|net| match net {
+        IpNet::V6(net) => Some(*net),
+        IpNet::V4(_) => None,
+    });
+
+    Ok(TunnelMetadata {
+        name,
+        server_public_key: server.public_key.clone(),
+        client_ip: subnet.addr(),
+        client_ipv6: subnet_v6.map(|net| net.addr()),
+        host,
+        settings: ServerSettings {
+            subnet: subnet.trunc(),
+            subnet_v6: subnet_v6.map(|net| net.trunc()),
+            listen_port,
+            ..ServerSettings::default()
+        },
+        routes: RouteProfile::default(),
+        allowed_ips: Some(server.allowed_ips.clone()),
+        client: ClientSettings {
+            dns: config.interface.dns.clone(),
+            dns_search: config.interface.dns_search.clone(),
+            mtu: config.interface.mtu,
+            persistent_keepalive: server.persistent_keepalive,
+        },
+        has_preshared_key: server.preshared_key.is_some(),
+        ssh_user: None,
+        ssh_port: None,
+        host_key_fingerprint: None,
+        jump_hosts: Vec::new(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Conf,
+    QrCode,
+}
+
+#[tauri::command]
+pub async fn export_config(
+    app: AppHandle,
+    host: Host,
+    tunnel_mode: TunnelMode,
+    format: ExportFormat,
+    path: PathBuf,
+) -> Result<(), String> {
+    let metadata = get_metadata_from_store(&app, host.to_string())?
+        .ok_or_else(|| format!("No metadata found for {}", host))?;
+    let config = client_config(&app, &metadata, tunnel_mode).await?;
+
+    let contents = match format {
+        ExportFormat::Conf => with_private_key_warning(&config).into_bytes(),
+        ExportFormat::QrCode => config_qr_png(&config).map_err(|e| e.to_string())?,
+    };
+
+    write_private_file(&path, &contents)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn write_private_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents)
 }
@@ -1,25 +1,74 @@
-use std::{net::Ipv4Addr, path::PathBuf};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+};
 
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Manager};
 use tauri_plugin_store::StoreExt;
-use vpn_lib::wireguard::server::SetupResult;
+use vpn_lib::{
+    host::Host,
+    wireguard::{routes::RouteProfile, server::SetupResult, settings::{ClientSettings, ServerSettings}},
+};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TunnelMetadata {
     pub name: String,
     pub server_public_key: String,
     pub client_ip: Ipv4Addr,
-    pub public_ip: Ipv4Addr,
+    #[serde(default)]
+    pub client_ipv6: Option<Ipv6Addr>,
+    #[serde(alias = "public_ip")]
+    pub host: Host,
+    #[serde(default)]
+    pub settings: ServerSettings,
+    #[serde(default)]
+    pub routes: RouteProfile,
+    /// Only set for imported configs, which use them as they are in either mode.
+    #[serde(default)]
+    pub allowed_ips: Option<Vec<IpNet>>,
+    /// Flattened so tunnels saved with only a top-level `dns` list still load.
+    #[serde(flatten)]
+    pub client: ClientSettings,
+    #[serde(default)]
+    pub has_preshared_key: bool,
+    #[serde(default)]
+    pub ssh_user: Option<String>,
+    #[serde(default)]
+    pub ssh_port: Option<u16>,
+    #[serde(default)]
+    pub host_key_fingerprint: Option<String>,
+    #[serde(default)]
+    pub jump_hosts: Vec<JumpHost>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JumpHost {
+    pub host: Host,
+    pub port: u16,
+    pub user: String,
+    #[serde(default)]
+    pub host_key_fingerprint: Option<String>,
 }
 
 impl From<SetupResult> for TunnelMetadata {
     fn from(result: SetupResult) -> Self {
         Self {
-            name: format!("VPN-{}", result.public_ip),
+            name: format!("VPN-{}", result.host),
             server_public_key: result.server_public_key,
             client_ip: result.client_ip,
-            public_ip: result.public_ip,
+            client_ipv6: result.client_ipv6,
+            host: result.host,
+            client: result.settings.client_settings(),
+            settings: result.settings,
+            routes: RouteProfile::default(),
+            allowed_ips: None,
+            has_preshared_key: result.client_preshared_key.is_some(),
+            ssh_user: None,
+            ssh_port: None,
+            host_key_fingerprint: None,
+            jump_hosts: Vec::new(),
         }
     }
 }
@@ -36,7 +85,7 @@ pub fn save_metadata_to_store(app: &AppHandle, data: TunnelMetadata) -> Result<(
     let store = app.store(store_path).map_err(|e| e.to_string())?;
 
     store.set(
-        data.public_ip.to_string(),
+        data.host.to_string(),
         serde_json::to_value(&data).map_err(|e| e.to_string())?,
     );
 
@@ -45,6 +94,21 @@ pub fn save_metadata_to_store(app: &AppHandle, data: TunnelMetadata) -> Result<(
     Ok(())
 }
 
+pub fn get_metadata_from_store(
+    app: &AppHandle,
+    key: String,
+) -> Result<Option<TunnelMetadata>, String> {
+    let store_path = get_store_path(&app)?;
+    let store = app.store(store_path).map_err(|e| e.to_string())?;
+
+    match store.get(&key) {
+        Some(value) => serde_json::from_value(value)
+            .map(Some)
+            .map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
 pub fn remove_metadata_from_store(app: &AppHandle, key: String) -> Result<(), String> {
     let store_path = get_store_path(&app)?;
     let store = app.store(store_path).map_err(|e| e.to_string())?;
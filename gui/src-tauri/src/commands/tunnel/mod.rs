@@ -1,5 +1,6 @@
 pub mod metadata;
 pub mod tunnel;
 pub mod configs;
+pub mod server;
 
 pub use tunnel::*;
\ No newline at end of file
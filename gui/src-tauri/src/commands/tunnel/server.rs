@@ -0,0 +1,122 @@
+use secrecy::ExposeSecret;
+use tauri::AppHandle;
+use tokio_util::sync::CancellationToken;
+use vpn_lib::{
+    host::Host,
+    wireguard::{
+        peer,
+        server::{public_key_for, teardown_wireguard},
+        stats::{self, PeerStats},
+    },
+};
+
+use crate::{
+    commands::{
+        tunnel::{
+            connect_to_server,
+            metadata::{
+                get_metadata_from_store, remove_metadata_from_store, save_metadata_to_store,
+            },
+            stop_tunnel, ServerLogin,
+        },
+        utils::{delete_keys_securely, load_key_securely, save_preshared_key_securely},
+    },
+    TunnelState,
+};
+
+#[tauri::command]
+pub async fn enable_preshared_key(
+    app: AppHandle,
+    host: Host,
+    login: ServerLogin,
+) -> Result<(), String> {
+    let mut metadata = get_metadata_from_store(&app, host.to_string())?
+        .ok_or_else(|| format!("No metadata found for {}", host))?;
+
+    let private_key =
+        load_key_securely(&app, &host).map_err(|e| format!("Failed to load private key: {}", e))?;
+    let public_key = public_key_for(private_key.expose_secret()).map_err(|e| e.to_string())?;
+
+    let mut jump_hosts = metadata.jump_hosts.clone();
+    let session = connect_to_server(
+        &app,
+        &host,
+        login,
+        &mut jump_hosts,
+        CancellationToken::new(),
+    )
+    .await?;
+
+    let preshared_key = peer::enable_preshared_key(&session, &host, &public_key)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    save_preshared_key_securely(&app, &host, &preshared_key).await?;
+
+    metadata.has_preshared_key = true;
+    metadata.jump_hosts = jump_hosts;
+    save_metadata_to_store(&app, metadata)
+}
+
+#[tauri::command]
+pub async fn teardown_server(
+    app: AppHandle,
+    tunnel_state: tauri::State<'_, TunnelState>,
+    host: Host,
+    login: ServerLogin,
+) -> Result<(), String> {
+    let metadata = get_metadata_from_store(&app, host.to_string())?
+        .ok_or_else(|| format!("No metadata found for {}", host))?;
+
+    // A full tunnel carries the SSH session itself, which would die with the
+    // interface halfway through the teardown.
+    let is_active = {
+        let active_tunnel = tunnel_state.active_tunnel.lock().unwrap();
+        active_tunnel.as_ref() == Some(&host.to_string())
+    };
+
+    if is_active {
+        stop_tunnel(app.clone(), tunnel_state).await?;
+    }
+
+    let mut jump_hosts = metadata.jump_hosts;
+    let session = connect_to_server(
+        &app,
+        &host,
+        login,
+        &mut jump_hosts,
+        CancellationToken::new(),
+    )
+    .await?;
+
+    teardown_wireguard(&session)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    delete_keys_securely(&app, &host)?;
+    remove_metadata_from_store(&app, host.to_string())
+}
+
+#[tauri::command]
+pub async fn peer_stats(
+    app: AppHandle,
+    host: Host,
+    login: ServerLogin,
+) -> Result<Vec<PeerStats>, String> {
+    let metadata = get_metadata_from_store(&app, host.to_string())?
+        .ok_or_else(|| format!("No metadata found for {}", host))?;
+
+    let mut jump_hosts = metadata.jump_hosts;
+    let session = connect_to_server(
+        &app,
+        &host,
+        login,
+        &mut jump_hosts,
+        CancellationToken::new(),
+    )
+    .await?;
+
+    stats::peer_stats(&session, &host)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -1,26 +1,39 @@
-use std::{fs, net::Ipv4Addr, path::PathBuf, str::FromStr};
+use std::{fs, path::PathBuf, sync::Mutex};
 
-use secrecy::ExposeSecret;
-use serde::Serialize;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter, Manager, State};
-use tauri_plugin_store::StoreExt;
 use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
 use vpn_lib::utils::create_command;
 use vpn_lib::{
     self,
+    host::{Host, HostError},
     network::ping_endpoint,
-    ssh::{connect_ssh, harden_ssh},
+    ssh::{connect_ssh, harden_ssh, SshAuth, SshClient, SshTarget},
     validate_key_file,
-    wireguard::server::{build_client_config, setup_wireguard, TunnelMode},
+    SshError,
+    wireguard::client::interface_name,
+    wireguard::provision::{plan_setup, ExistingInstall, SetupPlan},
+    wireguard::server::{
+        build_client_config, setup_wireguard, SetupProgress, SetupStep, TunnelMode,
+    },
+    wireguard::routes::allowed_ips,
+    wireguard::settings::{random_ula_subnet, ServerSettings},
 };
 
-use crate::commands::tunnel::metadata::get_store_path;
 use crate::{
     commands::{
         tunnel::{
-            metadata::{get_all_tunnels, save_metadata_to_store, TunnelMetadata}
+            metadata::{
+                get_all_tunnels, get_metadata_from_store, save_metadata_to_store, JumpHost,
+                TunnelMetadata,
+            },
+        },
+        utils::{
+            load_key_securely, load_preshared_key_securely, save_key_securely,
+            save_preshared_key_securely,
         },
-        utils::{load_key_securely, save_key_securely},
     },
     TunnelPayload, TunnelState,
 };
@@ -31,43 +44,289 @@ pub struct ConnectResponse {
     pub success: bool,
 }
 
+pub struct SetupHandle(pub Mutex<Option<CancellationToken>>);
+
+impl SetupHandle {
+    fn start(&self) -> CancellationToken {
+        let cancel_token = CancellationToken::new();
+        *self.0.lock().unwrap() = Some(cancel_token.clone());
+        cancel_token
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct HostKeyMismatchPayload {
+    pub server: String,
+    pub host: String,
+    pub expected: String,
+    pub found: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "method", rename_all = "lowercase", rename_all_fields = "camelCase")]
+pub enum SshAuthInput {
+    Key {
+        key_file: String,
+        passphrase: Option<String>,
+    },
+    Agent,
+    Password {
+        password: String,
+    },
+}
+
+impl TryFrom<SshAuthInput> for SshAuth {
+    type Error = String;
+
+    fn try_from(input: SshAuthInput) -> Result<Self, Self::Error> {
+        match input {
+            SshAuthInput::Key {
+                key_file,
+                passphrase,
+            } => {
+                let path = PathBuf::from(&key_file);
+                validate_key_file(&path).map_err(|e| e.to_string())?;
+
+                Ok(SshAuth::KeyFile {
+                    path,
+                    passphrase: passphrase
+                        .filter(|p| !p.is_empty())
+                        .map(|p| SecretString::new(p.into())),
+                })
+            }
+            SshAuthInput::Agent => Ok(SshAuth::Agent),
+            SshAuthInput::Password { password } => {
+                Ok(SshAuth::Password(SecretString::new(password.into())))
+            }
+        }
+    }
+}
+
+#[tauri::command]
+pub fn key_requires_passphrase(key_file: String) -> Result<bool, String> {
+    let key_path = PathBuf::from(&key_file);
+
+    validate_key_file(&key_path).map_err(|e| e.to_string())?;
+
+    Ok(vpn_lib::ssh::key_requires_passphrase(&key_path))
+}
+
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn setup_server(
     app: AppHandle,
+    setup_handle: State<'_, SetupHandle>,
     name: String,
-    server_ip: String,
+    server_host: String,
     port: Option<u16>,
     user: String,
-    key_file: String,
+    auth: SshAuthInput,
+    jump_hosts: Option<Vec<JumpHost>>,
+    sudo_password: Option<String>,
+    settings: Option<ServerSettings>,
+    dual_stack: Option<bool>,
+    existing: Option<ExistingInstall>,
 ) -> Result<(), String> {
-    let ip: Ipv4Addr = server_ip
-        .parse()
-        .map_err(|_| "Invalid IP address format".to_string())?;
-
-    let key_path = PathBuf::from(&key_file);
-
-    validate_key_file(&key_path).map_err(|e| e.to_string())?;
+    let settings = prepare_settings(settings, dual_stack)?;
+    let server: Host = server_host.parse().map_err(|e: HostError| e.to_string())?;
+    let cancel_token = setup_handle.start();
+    // Hardening turns password logins off, which would leave a password
+    // user with no way back in for later server actions.
+    let password_login = matches!(auth, SshAuthInput::Password { .. });
+
+    let login = ServerLogin {
+        port,
+        user: user.clone(),
+        auth,
+        sudo_password,
+    };
+    let mut jump_hosts = jump_hosts.unwrap_or_default();
+    let session = connect_to_server(&app, &server, login, &mut jump_hosts, cancel_token).await?;
 
-    let port = port.unwrap_or(22);
-    let session = connect_ssh(ip, port, user.clone(), key_path)
-        .await
-        .map_err(|e| e.to_string())?;
+    let report_progress = |progress: SetupProgress| {
+        let _ = app.emit("setup-progress", progress);
+    };
 
-    let result = setup_wireguard(&session, ip, "eth0".into())
-        .await
-        .map_err(|e| e.to_string())?;
+    let result = setup_wireguard(
+        &session,
+        &server,
+        &settings,
+        existing.unwrap_or_default(),
+        &report_progress,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
 
     let mut metadata: TunnelMetadata = result.clone().into();
     metadata.name = name;
+    metadata.host_key_fingerprint = Some(session.host_key_fingerprint.clone());
+    metadata.jump_hosts = jump_hosts;
+    metadata.ssh_user = Some(user);
+    metadata.ssh_port = port;
 
-    save_key_securely(&app, result.public_ip, &result.client_private_key)
+    save_key_securely(&app, &result.host, &result.client_private_key)
         .await
         .map_err(|e| e.to_string())?;
 
+    if let Some(preshared_key) = &result.client_preshared_key {
+        save_preshared_key_securely(&app, &result.host, preshared_key).await?;
+    }
+
     save_metadata_to_store(&app, metadata)?;
 
+    if password_login {
+        return Ok(());
+    }
+
+    report_progress(SetupProgress::StepStarted {
+        step: SetupStep::Harden,
+    });
+
     harden_ssh(&session).await.map_err(|e| e.to_string())?;
 
+    report_progress(SetupProgress::StepFinished {
+        step: SetupStep::Harden,
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn plan_server_setup(
+    app: AppHandle,
+    setup_handle: State<'_, SetupHandle>,
+    server_host: String,
+    port: Option<u16>,
+    user: String,
+    auth: SshAuthInput,
+    jump_hosts: Option<Vec<JumpHost>>,
+    sudo_password: Option<String>,
+    settings: Option<ServerSettings>,
+    dual_stack: Option<bool>,
+    existing: Option<ExistingInstall>,
+) -> Result<SetupPlan, String> {
+    let settings = prepare_settings(settings, dual_stack)?;
+    let server: Host = server_host.parse().map_err(|e: HostError| e.to_string())?;
+    let cancel_token = setup_handle.start();
+
+    let login = ServerLogin {
+        port,
+        user,
+        auth,
+        sudo_password,
+    };
+    let mut jump_hosts = jump_hosts.unwrap_or_default();
+    let session = connect_to_server(&app, &server, login, &mut jump_hosts, cancel_token).await?;
+
+    plan_setup(&session, &server, &settings, existing.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn prepare_settings(
+    settings: Option<ServerSettings>,
+    dual_stack: Option<bool>,
+) -> Result<ServerSettings, String> {
+    let mut settings = settings.unwrap_or_default();
+    if dual_stack.unwrap_or(false) && settings.subnet_v6.is_none() {
+        settings.subnet_v6 = Some(random_ula_subnet());
+    }
+    settings.validate().map_err(|e| e.to_string())?;
+
+    Ok(settings)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerLogin {
+    pub port: Option<u16>,
+    pub user: String,
+    pub auth: SshAuthInput,
+    pub sudo_password: Option<String>,
+}
+
+pub(crate) async fn connect_to_server(
+    app: &AppHandle,
+    server: &Host,
+    login: ServerLogin,
+    jump_hosts: &mut [JumpHost],
+    cancel_token: CancellationToken,
+) -> Result<SshClient, String> {
+    let auth = SshAuth::try_from(login.auth)?;
+
+    let known = get_metadata_from_store(app, server.to_string())?;
+    let pinned_fingerprint = known.as_ref().and_then(|m| m.host_key_fingerprint.clone());
+
+    for jump_host in jump_hosts.iter_mut() {
+        jump_host.host_key_fingerprint = known
+            .as_ref()
+            .and_then(|m| m.jump_hosts.iter().find(|j| j.host == jump_host.host))
+            .and_then(|j| j.host_key_fingerprint.clone());
+    }
+
+    // The target's password must never reach a bastion, which may log it
+    // and most likely has a different one anyway: hops go through the agent.
+    let jump_auth = match &auth {
+        SshAuth::Password(_) => SshAuth::Agent,
+        other => other.clone(),
+    };
+
+    let jump_targets = jump_hosts
+        .iter()
+        .map(|j| SshTarget {
+            host: j.host.clone(),
+            port: j.port,
+            user: j.user.clone(),
+            auth: jump_auth.clone(),
+            pinned_fingerprint: j.host_key_fingerprint.clone(),
+            sudo_password: None,
+        })
+        .collect();
+
+    let target = SshTarget {
+        host: server.clone(),
+        port: login.port.unwrap_or(22),
+        user: login.user,
+        auth,
+        pinned_fingerprint,
+        sudo_password: login.sudo_password.map(SecretString::from),
+    };
+
+    let mut session = cancel_token
+        .run_until_cancelled(connect_ssh(target, jump_targets))
+        .await
+        .ok_or_else(|| SshError::Cancelled.to_string())?
+        .map_err(|e| {
+            if let SshError::HostKeyMismatch { host, expected, found } = &e {
+                let _ = app.emit(
+                    "host-key-mismatch",
+                    HostKeyMismatchPayload {
+                        server: server.to_string(),
+                        host: host.clone(),
+                        expected: expected.clone(),
+                        found: found.clone(),
+                    },
+                );
+            }
+            e.to_string()
+        })?;
+
+    session.cancel_token = cancel_token;
+
+    for (jump_host, fingerprint) in jump_hosts.iter_mut().zip(&session.jump_host_fingerprints) {
+        jump_host.host_key_fingerprint = Some(fingerprint.clone());
+    }
+
+    Ok(session)
+}
+
+#[tauri::command]
+pub async fn cancel_setup(setup_handle: State<'_, SetupHandle>) -> Result<(), String> {
+    let mut lock = setup_handle.0.lock().unwrap();
+    if let Some(token) = lock.take() {
+        token.cancel();
+    }
     Ok(())
 }
 
@@ -126,43 +385,68 @@ pub fn is_tunnel_active(name: String) -> bool {
     }
 }
 
+pub(crate) async fn client_config(
+    app: &AppHandle,
+    metadata: &TunnelMetadata,
+    tunnel_mode: TunnelMode,
+) -> Result<String, String> {
+    let client_private_key = load_key_securely(app, &metadata.host)
+        .map_err(|e| format!("Failed to load private key: {}", e))?;
+    let preshared_key = if metadata.has_preshared_key {
+        let key = load_preshared_key_securely(app, &metadata.host)
+            .map_err(|e| format!("Failed to load preshared key: {}", e))?;
+        Some(key)
+    } else {
+        None
+    };
+
+    let allowed_ips = match &metadata.allowed_ips {
+        Some(imported) => imported.clone(),
+        None => {
+            let endpoint = match tunnel_mode {
+                TunnelMode::Split => Some(
+                    metadata
+                        .host
+                        .resolve(metadata.settings.listen_port)
+                        .await
+                        .map_err(|e| e.to_string())?
+                        .ip(),
+                ),
+                TunnelMode::Full => None,
+            };
+            allowed_ips(tunnel_mode, &metadata.routes, &metadata.settings, endpoint)
+        }
+    };
+
+    Ok(build_client_config(
+        client_private_key.expose_secret(),
+        preshared_key.as_ref().map(|key| key.expose_secret()),
+        &metadata.server_public_key,
+        &metadata.host,
+        metadata.client_ip,
+        metadata.client_ipv6,
+        &metadata.settings,
+        &allowed_ips,
+        &metadata.client,
+    ))
+}
+
 #[tauri::command]
 pub async fn start_tunnel(
     app: AppHandle,
     tunnel_state: tauri::State<'_, TunnelState>,
-    public_ip: Ipv4Addr,
+    host: Host,
     tunnel_mode: TunnelMode,
 ) -> Result<(), String> {
-    let store_path = get_store_path(&app)?;
-
-    let store = app
-        .store(store_path)
-        .map_err(|e| format!("Failed to open store: {}", e))?;
-
-    let public_ip_str = public_ip.to_string();
-
-    let metadata_value = store
-        .get(&public_ip_str)
-        .ok_or_else(|| format!("No metadata found for {}", public_ip_str))?;
-
-    let client_ip = Ipv4Addr::from_str(metadata_value["client_ip"].as_str().unwrap_or("10.0.0.2"))
-        .map_err(|e| e.to_string())?;
-
-    let server_pub_key = metadata_value["server_public_key"].as_str().unwrap_or("");
+    let host_str = host.to_string();
 
-    let client_private_key = load_key_securely(&app, public_ip)
-        .map_err(|e| format!("Failed to load private key: {}", e))?;
+    let metadata = get_metadata_from_store(&app, host_str.clone())?
+        .ok_or_else(|| format!("No metadata found for {}", host_str))?;
 
-    let wg_config = build_client_config(
-        client_private_key.expose_secret(),
-        server_pub_key,
-        public_ip,
-        client_ip,
-        &tunnel_mode,
-    );
+    let wg_config = client_config(&app, &metadata, tunnel_mode).await?;
 
     let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    let config_path = app_dir.join(format!("{}.conf", public_ip_str));
+    let config_path = app_dir.join(format!("{}.conf", interface_name(&host)));
 
     fs::write(&config_path, wg_config)
         .map_err(|e| format!("Failed to write temp config: {}", e))?;
@@ -170,7 +454,7 @@ pub async fn start_tunnel(
     vpn_lib::wireguard::client::start_tunnel(&config_path).map_err(|e| e.to_string())?;
 
     let mut active_lock = tunnel_state.active_tunnel.lock().unwrap();
-    *active_lock = Some(public_ip_str.clone());
+    *active_lock = Some(host_str.clone());
 
     drop(active_lock);
 
@@ -180,7 +464,7 @@ pub async fn start_tunnel(
     app.emit(
         "tunnel-status",
         TunnelPayload {
-            name: Some(public_ip_str),
+            name: Some(host_str),
             is_active: true,
             mode: tunnel_mode,
         },
@@ -195,18 +479,19 @@ pub async fn stop_tunnel(
     app: AppHandle,
     tunnel_state: tauri::State<'_, TunnelState>,
 ) -> Result<(), String> {
-    let (mode, active_ip) = {
+    let (mode, active_host) = {
         let mut active_lock = tunnel_state.active_tunnel.lock().unwrap();
         let mode_lock = tunnel_state.mode.lock().unwrap();
         (*mode_lock, active_lock.take())
     };
 
-    if let Some(ip) = active_ip {
-        vpn_lib::wireguard::client::stop_tunnel(&ip).map_err(|e| e.to_string())?;
+    if let Some(host) = active_host {
+        let interface = tunnel_interface_name(&host);
+        vpn_lib::wireguard::client::stop_tunnel(&interface).map_err(|e| e.to_string())?;
 
         let app_dir = app.path().app_data_dir().ok();
         if let Some(path) = app_dir {
-            let config_path = path.join(format!("{}.conf", ip));
+            let config_path = path.join(format!("{}.conf", interface));
             let _ = fs::remove_file(config_path);
         }
     }
@@ -224,6 +509,12 @@ pub async fn stop_tunnel(
     Ok(())
 }
 
+pub fn tunnel_interface_name(key: &str) -> String {
+    key.parse::<Host>()
+        .map(|host| interface_name(&host))
+        .unwrap_or_else(|_| key.to_string())
+}
+
 #[tauri::command]
 pub async fn quick_connect(
     app: AppHandle,
@@ -242,7 +533,7 @@ pub async fn quick_connect(
     start_tunnel(
         app,
         tunnel_state,
-        best_node.public_ip,
+        best_node.host,
         tunnel_mode,
     )
     .await?;
@@ -259,7 +550,7 @@ async fn get_optimal_node(tunnels: &[TunnelMetadata]) -> Result<TunnelMetadata,
     for tunnel in tunnels {
         let t = tunnel.clone();
         tasks.push(tokio::spawn(async move {
-            if let Some(latency) = ping_endpoint(t.public_ip).await {
+            if let Some(latency) = ping_endpoint(&t.host).await {
                 return Some((t, latency));
             }
             None
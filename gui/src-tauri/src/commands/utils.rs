@@ -1,14 +1,76 @@
 use secrecy::{ExposeSecret, SecretString};
-#[cfg(target_os = "windows")]
-use std::{net::Ipv4Addr};
 use tauri::AppHandle;
+use vpn_lib::host::Host;
+
+// IPv6 colons are not allowed in Windows file names, which the DPAPI
+// backend derives from the account name.
+fn key_account_name(prefix: &str, host: &Host) -> String {
+    format!("{}_{}", prefix, host).replace(':', "_")
+}
 
 pub async fn save_key_securely(
     app: &AppHandle,
-    public_ip: Ipv4Addr,
+    host: &Host,
     private_key: &SecretString,
 ) -> Result<(), String> {
-    let account_name = format!("priv_key_{}", public_ip);
+    save_secret(app, &key_account_name("priv_key", host), private_key)
+}
+
+pub fn load_key_securely(app: &AppHandle, host: &Host) -> Result<SecretString, String> {
+    load_secret(app, &key_account_name("priv_key", host))
+}
+
+pub async fn save_preshared_key_securely(
+    app: &AppHandle,
+    host: &Host,
+    preshared_key: &SecretString,
+) -> Result<(), String> {
+    save_secret(app, &key_account_name("psk", host), preshared_key)
+}
+
+pub fn load_preshared_key_securely(app: &AppHandle, host: &Host) -> Result<SecretString, String> {
+    load_secret(app, &key_account_name("psk", host))
+}
+
+pub fn delete_keys_securely(app: &AppHandle, host: &Host) -> Result<(), String> {
+    for prefix in ["priv_key", "psk"] {
+        delete_secret(app, &key_account_name(prefix, host))?;
+    }
+
+    Ok(())
+}
+
+fn delete_secret(app: &AppHandle, account_name: &str) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    {
+        use std::{fs, io::ErrorKind};
+
+        use tauri::Manager;
+
+        let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        let key_path = app_dir.join(format!("{}.enc", account_name));
+
+        match fs::remove_file(key_path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.to_string()),
+            _ => {}
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let entry =
+            keyring::Entry::new("com.vpnapp.keys", account_name).map_err(|e| e.to_string())?;
+
+        match entry.delete_credential() {
+            Err(keyring::Error::NoEntry) | Ok(()) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+
+    Ok(())
+}
+
+fn save_secret(app: &AppHandle, account_name: &str, secret: &SecretString) -> Result<(), String> {
     println!(
         "DEBUG: Attempting to SAVE key for account: {}",
         account_name
@@ -25,7 +87,7 @@ pub async fn save_key_securely(
         fs::create_dir_all(&app_dir).map_err(|e| e.to_string())?;
         let key_path = app_dir.join(format!("{}.enc", account_name));
 
-        let encrypted = encrypt_data(private_key.expose_secret().as_bytes(), Scope::User)
+        let encrypted = encrypt_data(secret.expose_secret().as_bytes(), Scope::User)
             .map_err(|e| format!("Windows Encryption Failed: {}", e))?;
 
         fs::write(key_path, encrypted).map_err(|e| e.to_string())?;
@@ -34,18 +96,17 @@ pub async fn save_key_securely(
     #[cfg(not(target_os = "windows"))]
     {
         let entry =
-            keyring::Entry::new("com.vpnapp.keys", &account_name).map_err(|e| e.to_string())?;
+            keyring::Entry::new("com.vpnapp.keys", account_name).map_err(|e| e.to_string())?;
 
         entry
-            .set_password(private_key.expose_secret())
+            .set_password(secret.expose_secret())
             .map_err(|e| e.to_string())?;
     }
 
     Ok(())
 }
 
-pub fn load_key_securely(app: &AppHandle, public_ip: Ipv4Addr) -> Result<SecretString, String> {
-    let account_name = format!("priv_key_{}", public_ip);
+fn load_secret(app: &AppHandle, account_name: &str) -> Result<SecretString, String> {
     println!(
         "DEBUG: Attempting to LOAD key for account: {}",
         account_name
@@ -70,7 +131,7 @@ pub fn load_key_securely(app: &AppHandle, public_ip: Ipv4Addr) -> Result<SecretS
     #[cfg(not(target_os = "windows"))]
     {
         let entry =
-            keyring::Entry::new("com.vpnapp.keys", &account_name).map_err(|e| e.to_string())?;
+            keyring::Entry::new("com.vpnapp.keys", account_name).map_err(|e| e.to_string())?;
 
         let password = entry.get_password().map_err(|e| e.to_string())?;
 
@@ -14,7 +14,8 @@ use vpn_lib::wireguard::server::TunnelMode;
 
 use crate::commands::{
     pinger::PingHandle,
-    state::{start_monitoring, sync_tunnel_state}
+    state::{start_monitoring, sync_tunnel_state},
+    tunnel::SetupHandle,
 };
 
 #[derive(Default)]
@@ -61,6 +62,7 @@ pub fn run() {
         .manage(AppCache::default())
         .manage(TunnelState::default())
         .manage(PingHandle(Mutex::new(None)))
+        .manage(SetupHandle(Mutex::new(None)))
         .setup(|app| {
             #[cfg(desktop)]
             let _ = app.handle().plugin(tauri_plugin_single_instance::init(|app, args, cwd| {}));
@@ -138,6 +140,9 @@ pub fn run() {
         })
         .invoke_handler(tauri::generate_handler![
             commands::tunnel::setup_server,
+            commands::tunnel::plan_server_setup,
+            commands::tunnel::key_requires_passphrase,
+            commands::tunnel::cancel_setup,
             commands::tunnel::toggle_vpn,
             commands::tunnel::start_tunnel,
             commands::tunnel::stop_tunnel,
@@ -149,6 +154,14 @@ pub fn run() {
             commands::pinger::stop_ping_loop,
             commands::tunnel::configs::get_configs,
             commands::tunnel::configs::remove_config,
+            commands::tunnel::configs::trust_host_key,
+            commands::tunnel::configs::save_route_profile,
+            commands::tunnel::configs::save_client_settings,
+            commands::tunnel::configs::import_config,
+            commands::tunnel::configs::export_config,
+            commands::tunnel::server::enable_preshared_key,
+            commands::tunnel::server::teardown_server,
+            commands::tunnel::server::peer_stats,
         ])
         .on_window_event(|window, event| {
             if let tauri::WindowEvent::CloseRequested { api, .. } = event {
@@ -11,12 +11,126 @@ export interface TunnelPayload {
 
 export interface TunnelMetadata {
 	client_ip: string;
+	client_ipv6: string | null;
 	name: string;
 	server_public_key: string;
-	public_ip: string;
+	host: string;
+	settings: ServerSettings;
+	routes: RouteProfile;
+	allowed_ips: string[] | null;
+	dns: string[];
+	dns_search: string[];
+	mtu: number | null;
+	persistent_keepalive: number | null;
+	has_preshared_key: boolean;
+	ssh_user: string | null;
+	ssh_port: number | null;
+	host_key_fingerprint: string | null;
+	jump_hosts: JumpHost[];
 	location: GeoLocation;
 };
 
+export interface ServerSettings {
+	subnet: string;
+	subnet_v6: string | null;
+	listen_port: number;
+	interface: string;
+	mtu: number | null;
+	egress_interface: string | null;
+	firewall: FirewallBackend | null;
+	resolver: DnsResolver | null;
+	preshared_keys: boolean;
+	host_firewall: HostFirewall | null;
+};
+
+export interface ClientSettings {
+	dns: string[];
+	dns_search: string[];
+	mtu: number | null;
+	persistent_keepalive: number | null;
+};
+
+export type FirewallBackend = "iptables" | "nftables";
+
+export type DnsResolver = "unbound" | "dnsmasq";
+
+export type HostFirewall = "ufw" | "firewalld";
+
+export interface RouteProfile {
+	include: string[];
+	exclude: string[];
+};
+
+export interface JumpHost {
+	host: string;
+	port: number;
+	user: string;
+	host_key_fingerprint: string | null;
+};
+
+export type ExistingInstall = "abort" | "join" | "reinstall";
+
+export interface InstallationSummary {
+	interface: string;
+	managed: boolean;
+	peers: string[];
+};
+
+export interface SetupPlan {
+	os: string;
+	existing: InstallationSummary | null;
+	changes: string[];
+};
+
+export type SetupStep = "install" | "keygen" | "config_upload" | "firewall" | "interface_up" | "enable_service" | "resolver" | "harden";
+
+export type SetupProgress =
+	| { kind: "step_started"; step: SetupStep }
+	| { kind: "output"; step: SetupStep; chunk: string }
+	| { kind: "step_finished"; step: SetupStep };
+
+export const SETUP_STEP_LABELS: Record<SetupStep, string> = {
+	install: "Installing WireGuard",
+	keygen: "Generating keys",
+	config_upload: "Uploading configuration",
+	firewall: "Opening the host firewall",
+	interface_up: "Bringing the interface up",
+	enable_service: "Enabling the service",
+	resolver: "Installing the DNS resolver",
+	harden: "Hardening SSH"
+};
+
+export type SshAuthInput =
+	| { method: "key"; keyFile: string; passphrase: string | null }
+	| { method: "agent" }
+	| { method: "password"; password: string };
+
+export interface ServerLogin {
+	port: number | null;
+	user: string;
+	auth: SshAuthInput;
+	sudoPassword: string | null;
+};
+
+export interface PeerStats {
+	name: string | null;
+	public_key: string;
+	endpoint: string | null;
+	allowed_ips: string[];
+	latest_handshake: string | null;
+	online: boolean;
+	rx_bytes: number;
+	tx_bytes: number;
+	persistent_keepalive: number | null;
+};
+
+export interface HostKeyMismatchPayload {
+	server: string;
+	host: string;
+	expected: string;
+	found: string;
+};
+
 export interface UnifiedEndpoint {
 	geo: GeoLocation;
 	config: TunnelMetadata;
@@ -30,8 +144,7 @@ export async function getConfigurations(): Promise<TunnelMetadata[]> {
 
 	const locationsPromises = confs.map(async (conf) => {
 
-		const ip = conf.public_ip;
-		const res = await getGeoLocation(ip);
+		const res = await getGeoLocation(conf.host);
 
 		if (!res) return null;
 
@@ -57,9 +170,125 @@ export async function removeConfiguration(metadata: TunnelMetadata): Promise<Boo
 
 };
 
+export async function trustHostKey(server: string, host: string, fingerprint: string): Promise<boolean> {
+
+	const { error } = await runCommand("trust_host_key", true, {
+		server,
+		host,
+		fingerprint
+	});
+
+	return !error;
+
+};
+
+// Parses an `ssh -J` style chain: "user@host[:port], user@host[:port]".
+export function parseJumpHosts(chain: string): JumpHost[] | null {
+
+	const hops = chain.split(",").map((hop) => hop.trim()).filter(Boolean);
+	const parsed: JumpHost[] = [];
+
+	for (const hop of hops) {
+
+		const match = hop.match(/^([^@\s]+)@(\[[^\]\s]+\]|[^:\s]+)(?::(\d+))?$/);
+		if (!match) return null;
+
+		parsed.push({
+			user: match[1],
+			host: match[2],
+			port: match[3] ? parseInt(match[3]) : 22,
+			host_key_fingerprint: null
+		});
+
+	};
+
+	return parsed;
+
+};
+
+export async function saveRouteProfile(host: string, routes: RouteProfile): Promise<boolean> {
+
+	const { error } = await runCommand("save_route_profile", true, {
+		host,
+		routes
+	});
+
+	return !error;
+
+};
+
+export async function saveClientSettings(host: string, client: ClientSettings): Promise<boolean> {
+
+	const { error } = await runCommand("save_client_settings", true, {
+		host,
+		client
+	});
+
+	return !error;
+
+};
+
+export async function enablePresharedKey(host: string, login: ServerLogin): Promise<boolean> {
+
+	const { error } = await runCommand("enable_preshared_key", true, {
+		host,
+		login
+	});
+
+	return !error;
+
+};
+
+export async function teardownServer(host: string, login: ServerLogin): Promise<boolean> {
+
+	const { error } = await runCommand("teardown_server", true, {
+		host,
+		login
+	});
+
+	return !error;
+
+};
+
+export async function importConfig(path: string): Promise<boolean> {
+
+	const { error } = await runCommand("import_config", true, {
+		path
+	});
+
+	return !error;
+
+};
+
+export async function getPeerStats(host: string, login: ServerLogin): Promise<PeerStats[] | null> {
+
+	const { data } = await runCommand<PeerStats[]>("peer_stats", true, {
+		host,
+		login
+	});
+
+	return data;
+
+};
+
+export type ExportFormat = "conf" | "qr_code";
+
+export async function exportConfig(host: string, tunnelMode: TunnelMode, format: ExportFormat, path: string): Promise<boolean> {
+
+	const { error } = await runCommand("export_config", true, {
+		host,
+		tunnelMode,
+		format,
+		path
+	});
+
+	return !error;
+
+};
+
 export async function startTunnel(conf: TunnelMetadata, mode: TunnelMode) {
 	await runCommand("start_tunnel", true, {
-		publicIp: conf.public_ip,
+		host: conf.host,
 		tunnelMode: mode
 	});
 };
@@ -94,8 +323,7 @@ export async function getAvailableEndpoints(): Promise<UnifiedEndpoint[]> {
 
 	const locationsPromises = (confs as TunnelMetadata[]).map(async (conf) => {
 
-		const ip = conf.public_ip;
-		const res = await getGeoLocation(ip);
+		const res = await getGeoLocation(conf.host);
 
 		if (!res) return null;
 
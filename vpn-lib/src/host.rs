@@ -0,0 +1,207 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::net::lookup_host;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HostError {
+    #[error("Host cannot be empty")]
+    Empty,
+    #[error("{0} is not a valid IP address or hostname")]
+    Invalid(String),
+    #[error("Could not resolve {0}: {1}")]
+    Resolve(String, std::io::Error),
+}
+
+/// Serialized as a plain string so stored keys keep their old format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Host {
+    Ip(IpAddr),
+    Name(String),
+}
+
+impl Host {
+    pub fn with_port(&self, port: u16) -> String {
+        match self {
+            Host::Ip(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+            Host::Ip(ip) => format!("{}:{}", ip, port),
+            Host::Name(name) => format!("{}:{}", name, port),
+        }
+    }
+
+    pub async fn resolve(&self, port: u16) -> Result<SocketAddr, HostError> {
+        if let Host::Ip(ip) = self {
+            return Ok(SocketAddr::new(*ip, port));
+        }
+
+        lookup_host(self.with_port(port))
+            .await
+            .map_err(|e| HostError::Resolve(self.to_string(), e))?
+            .next()
+            .ok_or_else(|| {
+                HostError::Resolve(
+                    self.to_string(),
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses returned"),
+                )
+            })
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Ip(ip) => write!(f, "{}", ip),
+            Host::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl FromStr for Host {
+    type Err = HostError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(HostError::Empty);
+        }
+
+        let unbracketed = s
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+            .unwrap_or(s);
+
+        if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+            return Ok(Host::Ip(ip));
+        }
+
+        let name = s.trim_end_matches('.').to_ascii_lowercase();
+        let valid_label = |label: &str| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        };
+
+        if name.len() > 253 || !name.split('.').all(valid_label) {
+            return Err(HostError::Invalid(s.to_string()));
+        }
+
+        Ok(Host::Name(name))
+    }
+}
+
+impl TryFrom<String> for Host {
+    type Error = HostError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Host> for String {
+    fn from(host: Host) -> Self {
+        host.to_string()
+    }
+}
+
+impl From<IpAddr> for Host {
+    fn from(ip: IpAddr) -> Self {
+        Host::Ip(ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &str) -> Host {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(
+            parse("203.0.113.7"),
+            Host::Ip("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(
+            parse("2001:db8::1"),
+            Host::Ip("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(
+            parse("[2001:db8::1]"),
+            Host::Ip("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(parse("  10.0.0.1 "), Host::Ip("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn normalizes_hostnames() {
+        assert_eq!(
+            parse("VPN.Example.com."),
+            Host::Name("vpn.example.com".into())
+        );
+        assert_eq!(parse("my-server"), Host::Name("my-server".into()));
+    }
+
+    #[test]
+    fn rejects_invalid_hosts() {
+        assert!(matches!("".parse::<Host>(), Err(HostError::Empty)));
+        assert!(matches!("   ".parse::<Host>(), Err(HostError::Empty)));
+
+        for invalid in [
+            "-vpn.example.com",
+            "vpn-.example.com",
+            "vpn..example.com",
+            "vpn_1.example.com",
+            "[vpn.example.com]",
+            "[203.0.113.7",
+            "vpn.example.com:22",
+        ] {
+            assert!(
+                matches!(invalid.parse::<Host>(), Err(HostError::Invalid(_))),
+                "{invalid} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn limits_label_and_name_length() {
+        let label = "a".repeat(63);
+        assert!(label.parse::<Host>().is_ok());
+        assert!(format!("{label}a").parse::<Host>().is_err());
+
+        let name = [label.as_str(); 4].join(".");
+        assert_eq!(name.len(), 255);
+        assert!(name.parse::<Host>().is_err());
+        assert!(name[..253].parse::<Host>().is_ok());
+    }
+
+    #[test]
+    fn brackets_ipv6_endpoints() {
+        assert_eq!(parse("2001:db8::1").with_port(51820), "[2001:db8::1]:51820");
+        assert_eq!(parse("203.0.113.7").with_port(51820), "203.0.113.7:51820");
+        assert_eq!(
+            parse("vpn.example.com").with_port(51820),
+            "vpn.example.com:51820"
+        );
+    }
+
+    #[test]
+    fn serializes_as_a_plain_string() {
+        let host = parse("[2001:db8::1]");
+
+        assert_eq!(serde_json::to_string(&host).unwrap(), "\"2001:db8::1\"");
+        assert_eq!(
+            serde_json::from_str::<Host>("\"2001:db8::1\"").unwrap(),
+            host
+        );
+        assert!(serde_json::from_str::<Host>("\"not a host\"").is_err());
+    }
+}
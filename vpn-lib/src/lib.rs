@@ -2,14 +2,16 @@ pub mod utils;
 pub mod ssh;
 pub mod wireguard;
 pub mod network;
+pub mod host;
 
 use std::{
-    net::{IpAddr, Ipv4Addr},
     path::PathBuf,
     time::Duration,
 };
 use tokio::{net::TcpStream, time::timeout};
 
+use crate::host::Host;
+
 #[derive(Debug, thiserror::Error)]
 pub enum KeyFileError {
     #[error("Key file not found at {0}")]
@@ -27,19 +29,42 @@ pub enum KeyFileError {
 #[derive(Debug, thiserror::Error)]
 pub enum SshError {
     #[error("Failed to reach server at {0}: {1}")]
-    Network(IpAddr, std::io::Error),
+    Network(Host, std::io::Error),
     #[error("SSH handshake failed: {0}")]
     Protocol(#[from] ssh2::Error),
-    #[error("Authentication failed for user {0}. Check your private key")]
-    AuthFailed(String),
+    #[error("Authentication failed for user {user}. Check your {credential}")]
+    AuthFailed {
+        user: String,
+        credential: &'static str,
+    },
     #[error("The background task panicked")]
     ThreadError(#[from] tokio::task::JoinError),
     #[error("Handshake failed: {0}")]
     HandshakeFailed(String),
+    #[error("The private key is encrypted, a passphrase is required")]
+    PassphraseRequired,
+    #[error("ssh-agent is not available: {0}")]
+    AgentUnavailable(String),
+    #[error("User {0} needs a password to use sudo on this server")]
+    SudoPasswordRequired(String),
+    #[error("The sudo password for {0} was rejected")]
+    SudoAuthFailed(String),
+    #[error("User {0} cannot use sudo on this server: {1}")]
+    SudoUnavailable(String, String),
+    #[error("Remote command timed out after {after:?}: {command}")]
+    CommandTimedOut { command: String, after: Duration },
+    #[error("The operation was cancelled")]
+    Cancelled,
+    #[error("Host key for {host} has changed (pinned {expected}, server sent {found})")]
+    HostKeyMismatch {
+        host: String,
+        expected: String,
+        found: String,
+    },
 }
 
-pub async fn ping_server(addr: Ipv4Addr) -> bool {
-    match timeout(Duration::from_secs(3), TcpStream::connect((addr, 22))).await {
+pub async fn ping_server(host: &Host) -> bool {
+    match timeout(Duration::from_secs(3), TcpStream::connect((host.to_string(), 22))).await {
         Ok(Ok(_stream)) => true,
         _ => false,
     }
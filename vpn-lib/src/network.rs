@@ -1,14 +1,23 @@
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 
-use surge_ping::{Client, Config, PingIdentifier, PingSequence};
+use surge_ping::{Client, Config, ICMP, PingIdentifier, PingSequence};
 
-pub async fn ping_endpoint(ip: Ipv4Addr) -> Option<u128> {
-    let client = Client::new(&Config::default()).ok()?;
-    let mut pinger = client.pinger(ip.into(), PingIdentifier(0)).await;
+use crate::host::Host;
+
+pub async fn ping_endpoint(host: &Host) -> Option<u128> {
+    let ip: IpAddr = host.resolve(0).await.ok()?.ip();
+
+    let config = match ip {
+        IpAddr::V4(_) => Config::default(),
+        IpAddr::V6(_) => Config::builder().kind(ICMP::V6).build(),
+    };
+
+    let client = Client::new(&config).ok()?;
+    let mut pinger = client.pinger(ip, PingIdentifier(0)).await;
 
     let payload = [0u8; 56];
     match pinger.ping(PingSequence(0), &payload).await {
-        Ok((surge_ping::IcmpPacket::V4(_packet), duration)) => Some(duration.as_millis()),
+        Ok((_packet, duration)) => Some(duration.as_millis()),
         _ => None,
     }
 }
@@ -1,83 +1,665 @@
 use anyhow::Context;
-use russh::client::{AuthResult, Config, Handle, Handler};
-use russh::keys::ssh_key;
-use std::{net::Ipv4Addr, path::PathBuf, sync::Arc};
+use russh::{Channel, ChannelStream};
+use russh::client::{AuthResult, Config, Handle, Handler, Msg};
+use russh::keys::agent::client::AgentClient;
+use russh::keys::{HashAlg, ssh_key};
+use secrecy::{ExposeSecret, SecretString};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_util::sync::CancellationToken;
 
-use crate::SshError;
+use crate::{SshError, host::Host};
 
-pub struct ClientHandler;
+pub struct ClientHandler {
+    pinned_fingerprint: Option<String>,
+    seen_fingerprint: Arc<Mutex<Option<String>>>,
+}
 
 impl Handler for ClientHandler {
     type Error = russh::Error;
 
     async fn check_server_key(
         &mut self,
-        _server_public_key: &ssh_key::PublicKey,
+        server_public_key: &ssh_key::PublicKey,
     ) -> std::result::Result<bool, Self::Error> {
-        std::result::Result::Ok(true)
+        let fingerprint = server_public_key.fingerprint(HashAlg::Sha256).to_string();
+
+        let trusted = match &self.pinned_fingerprint {
+            Some(pinned) => *pinned == fingerprint,
+            None => true,
+        };
+
+        *self.seen_fingerprint.lock().unwrap() = Some(fingerprint);
+
+        std::result::Result::Ok(trusted)
     }
 }
 
 pub type SshSession = Handle<ClientHandler>;
 
+#[derive(Clone)]
+pub enum SshAuth {
+    KeyFile {
+        path: PathBuf,
+        passphrase: Option<SecretString>,
+    },
+    Agent,
+    /// Setup skips `harden_ssh` for these, which would lock the user out.
+    Password(SecretString),
+}
+
+impl SshAuth {
+    fn credential(&self) -> &'static str {
+        match self {
+            SshAuth::KeyFile { .. } => "private key",
+            SshAuth::Agent => "ssh-agent keys",
+            SshAuth::Password(_) => "password",
+        }
+    }
+}
+
+pub struct SshTarget {
+    pub host: Host,
+    pub port: u16,
+    pub user: String,
+    pub auth: SshAuth,
+    pub pinned_fingerprint: Option<String>,
+    /// Only used for the final target, never for jump hosts.
+    pub sudo_password: Option<SecretString>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteCommand {
+    program: String,
+    args: Vec<String>,
+    timeout: Option<Duration>,
+}
+
+impl RemoteCommand {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            timeout: None,
+        }
+    }
+
+    /// Pass values as `args` and refer to them as `$0`, `$1`... in the script.
+    pub fn shell(script: impl Into<String>) -> Self {
+        Self::new("sh").arg("-c").arg(script)
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn to_shell_string(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|part| shell_quote(part))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+pub fn shell_quote(value: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+
+    if !value.is_empty() && value.chars().all(is_safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
 pub struct SshClient {
     pub session: SshSession,
     pub sudo_prefix: String,
+    pub host_key_fingerprint: String,
+    pub jump_host_fingerprints: Vec<String>,
+    pub command_timeout: Duration,
+    pub cancel_token: CancellationToken,
+    sudo_password: Option<SecretString>,
+    // Keeps the tunnels the session runs through open for its whole lifetime.
+    jump_sessions: Vec<SshSession>,
 }
 
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+
 impl SshClient {
-    pub fn new(session: SshSession, user: &str) -> Self {
+    pub fn new(session: SshSession, user: &str, host_key_fingerprint: String) -> Self {
         let sudo_prefix = if user == "root" { "" } else { "sudo " }.to_string();
         Self {
             session,
             sudo_prefix,
+            host_key_fingerprint,
+            jump_host_fingerprints: Vec::new(),
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            cancel_token: CancellationToken::new(),
+            sudo_password: None,
+            jump_sessions: Vec::new(),
         }
     }
 
-    pub async fn exec(&self, cmd: &str) -> anyhow::Result<(String, i32)> {
+    /// `-k` makes sudo ask every time, so the password line is always
+    /// consumed by sudo and never reaches the command's own stdin.
+    pub fn set_sudo_password(&mut self, password: SecretString) {
+        if self.sudo_prefix.is_empty() {
+            return;
+        }
+
+        self.sudo_prefix = "sudo -k -S -p '' ".to_string();
+        self.sudo_password = Some(password);
+    }
+
+    async fn exec_privileged(
+        &self,
+        cmd: &str,
+        input: Option<&[u8]>,
+        on_output: &mut (dyn FnMut(OutputChunk) + Send),
+        timeout: Duration,
+    ) -> anyhow::Result<(String, i32)> {
         let full_cmd = format!("{}{}", self.sudo_prefix, cmd);
-        run_remote_cmd(&self.session, &full_cmd).await
+
+        let stdin = match &self.sudo_password {
+            Some(password) => {
+                let mut stdin = format!("{}\n", password.expose_secret()).into_bytes();
+                stdin.extend_from_slice(input.unwrap_or_default());
+                Some(stdin)
+            }
+            None => input.map(<[u8]>::to_vec),
+        };
+
+        run_remote_cmd_with(
+            &self.session,
+            &full_cmd,
+            stdin.as_deref(),
+            on_output,
+            &self.limits(timeout),
+        )
+        .await
+    }
+
+    fn limits(&self, timeout: Duration) -> CommandLimits {
+        CommandLimits {
+            timeout: Some(timeout),
+            cancel: Some(self.cancel_token.clone()),
+        }
+    }
+
+    pub fn ensure_not_cancelled(&self) -> Result<(), SshError> {
+        if self.cancel_token.is_cancelled() {
+            return Err(SshError::Cancelled);
+        }
+
+        Ok(())
+    }
+
+    pub async fn exec(&self, cmd: &str) -> anyhow::Result<(String, i32)> {
+        self.exec_with_timeout(cmd, self.command_timeout).await
+    }
+
+    pub async fn exec_with_timeout(
+        &self,
+        cmd: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<(String, i32)> {
+        self.exec_privileged(cmd, None, &mut |_| {}, timeout).await
     }
 
     pub async fn exec_raw(&self, cmd: &str) -> anyhow::Result<(String, i32)> {
-        run_remote_cmd(&self.session, cmd).await
+        run_remote_cmd_with(
+            &self.session,
+            cmd,
+            None,
+            &mut |_| {},
+            &self.limits(self.command_timeout),
+        )
+        .await
+    }
+
+    pub async fn exec_streaming(
+        &self,
+        cmd: &str,
+        timeout: Duration,
+        on_output: &mut (dyn FnMut(OutputChunk) + Send),
+    ) -> anyhow::Result<(String, i32)> {
+        self.exec_privileged(cmd, None, on_output, timeout).await
+    }
+
+    pub async fn run(&self, cmd: &RemoteCommand) -> anyhow::Result<(String, i32)> {
+        let timeout = cmd.timeout.unwrap_or(self.command_timeout);
+        self.exec_with_timeout(&cmd.to_shell_string(), timeout).await
+    }
+
+    pub async fn run_with_input(
+        &self,
+        cmd: &RemoteCommand,
+        input: &[u8],
+    ) -> anyhow::Result<(String, i32)> {
+        let timeout = cmd.timeout.unwrap_or(self.command_timeout);
+        self.exec_privileged(&cmd.to_shell_string(), Some(input), &mut |_| {}, timeout)
+            .await
+    }
+
+    pub async fn run_raw(&self, cmd: &RemoteCommand) -> anyhow::Result<(String, i32)> {
+        let timeout = cmd.timeout.unwrap_or(self.command_timeout);
+        run_remote_cmd_with(
+            &self.session,
+            &cmd.to_shell_string(),
+            None,
+            &mut |_| {},
+            &self.limits(timeout),
+        )
+        .await
+    }
+
+    pub async fn run_streaming(
+        &self,
+        cmd: &RemoteCommand,
+        on_output: &mut (dyn FnMut(OutputChunk) + Send),
+    ) -> anyhow::Result<(String, i32)> {
+        let timeout = cmd.timeout.unwrap_or(self.command_timeout);
+        self.exec_streaming(&cmd.to_shell_string(), timeout, on_output)
+            .await
+    }
+
+    pub async fn upload(
+        &self,
+        remote_path: &Path,
+        content: &[u8],
+        mode: u32,
+        owner: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let path = remote_path.display();
+        let tmp_path = format!("{}.tmp", path);
+
+        let write_cmd = RemoteCommand::shell("umask 077 && cat > \"$0\"").arg(&tmp_path);
+        let (output, status) = self
+            .exec_privileged(
+                &write_cmd.to_shell_string(),
+                Some(content),
+                &mut |_| {},
+                self.command_timeout,
+            )
+            .await?;
+
+        if status != 0 {
+            anyhow::bail!("Failed to upload file to {}: {}", path, output);
+        }
+
+        let result = self.finish_upload(&tmp_path, remote_path, content, mode, owner).await;
+
+        if result.is_err() {
+            let _ = self
+                .run(&RemoteCommand::new("rm").args(["-f", tmp_path.as_str()]))
+                .await;
+        }
+
+        result
+    }
+
+    async fn finish_upload(
+        &self,
+        tmp_path: &str,
+        remote_path: &Path,
+        content: &[u8],
+        mode: u32,
+        owner: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let chmod = RemoteCommand::new("chmod").args([format!("{:o}", mode), tmp_path.into()]);
+        let (output, status) = self.run(&chmod).await?;
+        if status != 0 {
+            anyhow::bail!("Failed to set mode on {}: {}", tmp_path, output);
+        }
+
+        if let Some(owner) = owner {
+            let (output, status) = self
+                .run(&RemoteCommand::new("chown").args([owner, tmp_path]))
+                .await?;
+            if status != 0 {
+                anyhow::bail!("Failed to set owner on {}: {}", tmp_path, output);
+            }
+        }
+
+        let (output, status) = self
+            .run(&RemoteCommand::new("sha256sum").arg(tmp_path))
+            .await?;
+        if status != 0 {
+            anyhow::bail!("Failed to checksum {}: {}", tmp_path, output);
+        }
+
+        let expected = sha256_hex(content);
+        let remote = output.split_whitespace().next().unwrap_or_default();
+        if remote != expected {
+            anyhow::bail!(
+                "Checksum mismatch after uploading {}: expected {}, got {}",
+                remote_path.display(),
+                expected,
+                remote
+            );
+        }
+
+        let mv = RemoteCommand::new("mv").args([
+            "-f".to_string(),
+            tmp_path.to_string(),
+            remote_path.display().to_string(),
+        ]);
+        let (output, status) = self.run(&mv).await?;
+        if status != 0 {
+            anyhow::bail!("Failed to move {} into place: {}", remote_path.display(), output);
+        }
+
+        anyhow::Ok(())
     }
 }
 
+fn sha256_hex(content: &[u8]) -> String {
+    HashAlg::Sha256
+        .digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 pub async fn connect_ssh(
-    addr: Ipv4Addr,
-    port: u16,
-    user: String,
-    key_path: PathBuf,
+    target: SshTarget,
+    jump_hosts: Vec<SshTarget>,
 ) -> std::result::Result<SshClient, SshError> {
-    let config = Config::default();
-    let config = Arc::new(config);
-    let sh = ClientHandler;
+    let mut jump_sessions: Vec<SshSession> = Vec::new();
+    let mut jump_host_fingerprints = Vec::new();
+
+    for hop in jump_hosts {
+        let (session, fingerprint) = match jump_sessions.last() {
+            Some(previous) => {
+                let stream = open_direct_tcpip(previous, &hop).await?;
+                establish_session(stream, hop).await?
+            }
+            None => {
+                let stream = open_tcp(&hop).await?;
+                establish_session(stream, hop).await?
+            }
+        };
+
+        jump_sessions.push(session);
+        jump_host_fingerprints.push(fingerprint);
+    }
+
+    let user = target.user.clone();
+    let sudo_password = target.sudo_password.clone();
+    let (session, host_key_fingerprint) = match jump_sessions.last() {
+        Some(previous) => {
+            let stream = open_direct_tcpip(previous, &target).await?;
+            establish_session(stream, target).await?
+        }
+        None => {
+            let stream = open_tcp(&target).await?;
+            establish_session(stream, target).await?
+        }
+    };
+
+    let mut client = SshClient::new(session, &user, host_key_fingerprint);
+    client.jump_host_fingerprints = jump_host_fingerprints;
+    client.jump_sessions = jump_sessions;
+
+    if user != "root" {
+        check_sudo(&mut client, &user, sudo_password).await?;
+    }
+
+    std::result::Result::Ok(client)
+}
+
+async fn check_sudo(
+    client: &mut SshClient,
+    user: &str,
+    sudo_password: Option<SecretString>,
+) -> std::result::Result<(), SshError> {
+    let probe = |e: anyhow::Error| SshError::HandshakeFailed(format!("Sudo check failed: {}", e));
+
+    let (output, status) = client
+        .run_raw(&RemoteCommand::new("sudo").args(["-n", "true"]))
+        .await
+        .map_err(probe)?;
+
+    if status == 0 {
+        return Ok(());
+    }
+
+    if status == 127 {
+        return Err(SshError::SudoUnavailable(user.to_string(), output.trim().to_string()));
+    }
+
+    let Some(password) = sudo_password else {
+        return Err(SshError::SudoPasswordRequired(user.to_string()));
+    };
+
+    client.set_sudo_password(password);
 
-    let key_pair = russh::keys::load_secret_key(key_path, None)
-        .map_err(|e| SshError::HandshakeFailed(format!("Failed to load key: {}", e)))?;
+    let (output, status) = client
+        .run(&RemoteCommand::new("true"))
+        .await
+        .map_err(probe)?;
+
+    match status {
+        0 => Ok(()),
+        _ if output.contains("not in the sudoers") || output.contains("not allowed") => Err(
+            SshError::SudoUnavailable(user.to_string(), output.trim().to_string()),
+        ),
+        _ => Err(SshError::SudoAuthFailed(user.to_string())),
+    }
+}
 
-    let mut session = russh::client::connect::<ClientHandler, _>(config, (addr, port), sh)
+async fn open_tcp(target: &SshTarget) -> std::result::Result<TcpStream, SshError> {
+    let stream = TcpStream::connect((target.host.to_string(), target.port))
         .await
-        .map_err(|e| SshError::HandshakeFailed(format!("Connection failed: {}", e)))?;
+        .map_err(|e| SshError::Network(target.host.clone(), e))?;
+    stream.set_nodelay(true).ok();
 
-    let key_with_alg = russh::keys::PrivateKeyWithHashAlg::new(Arc::new(key_pair), None);
+    Ok(stream)
+}
 
-    let auth_res = session
-        .authenticate_publickey(user.clone(), key_with_alg)
+async fn open_direct_tcpip(
+    jump_session: &SshSession,
+    target: &SshTarget,
+) -> std::result::Result<ChannelStream<Msg>, SshError> {
+    let channel = jump_session
+        .channel_open_direct_tcpip(target.host.to_string(), target.port as u32, "127.0.0.1", 0)
         .await
-        .map_err(|e| SshError::HandshakeFailed(format!("Auth request failed: {}", e)))?;
+        .map_err(|e| {
+            SshError::HandshakeFailed(format!("Jump host could not reach {}: {}", target.host, e))
+        })?;
+
+    Ok(channel.into_stream())
+}
+
+async fn establish_session<S>(
+    stream: S,
+    target: SshTarget,
+) -> std::result::Result<(SshSession, String), SshError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let config = Config::default();
+    let config = Arc::new(config);
+    let seen_fingerprint = Arc::new(Mutex::new(None));
+    let sh = ClientHandler {
+        pinned_fingerprint: target.pinned_fingerprint.clone(),
+        seen_fingerprint: seen_fingerprint.clone(),
+    };
+
+    let connect_res = russh::client::connect_stream(config, stream, sh).await;
+    let host_key_fingerprint = seen_fingerprint.lock().unwrap().take().unwrap_or_default();
+
+    let mut session = match connect_res {
+        Ok(session) => session,
+        Err(russh::Error::UnknownKey) => {
+            return Err(SshError::HostKeyMismatch {
+                host: target.host.to_string(),
+                expected: target.pinned_fingerprint.unwrap_or_default(),
+                found: host_key_fingerprint,
+            });
+        }
+        Err(e) => return Err(SshError::HandshakeFailed(format!("Connection failed: {}", e))),
+    };
+
+    let user = target.user;
+    let credential = target.auth.credential();
+    let auth_res = match target.auth {
+        SshAuth::KeyFile { path, passphrase } => {
+            let key_pair = load_key_file(&path, passphrase.as_ref())?;
+            let key_with_alg = russh::keys::PrivateKeyWithHashAlg::new(Arc::new(key_pair), None);
+
+            session
+                .authenticate_publickey(user.clone(), key_with_alg)
+                .await
+                .map_err(|e| SshError::HandshakeFailed(format!("Auth request failed: {}", e)))?
+        }
+        SshAuth::Agent => authenticate_with_agent(&mut session, &user).await?,
+        SshAuth::Password(password) => session
+            .authenticate_password(user.clone(), password.expose_secret())
+            .await
+            .map_err(|e| SshError::HandshakeFailed(format!("Auth request failed: {}", e)))?,
+    };
 
     match auth_res {
-        AuthResult::Success => {
-            let client = SshClient::new(session, &user);
-            std::result::Result::Ok(client)
+        AuthResult::Success => std::result::Result::Ok((session, host_key_fingerprint)),
+        _ => std::result::Result::Err(SshError::AuthFailed { user, credential }),
+    }
+}
+
+fn load_key_file(
+    path: &PathBuf,
+    passphrase: Option<&SecretString>,
+) -> std::result::Result<ssh_key::PrivateKey, SshError> {
+    russh::keys::load_secret_key(path, passphrase.map(|p| p.expose_secret()))
+        .map_err(|e| match e {
+            russh::keys::Error::KeyIsEncrypted => SshError::PassphraseRequired,
+            e => SshError::HandshakeFailed(format!("Failed to load key: {}", e)),
+        })
+}
+
+pub fn key_requires_passphrase(path: &PathBuf) -> bool {
+    matches!(load_key_file(path, None), Err(SshError::PassphraseRequired))
+}
+
+async fn authenticate_with_agent(
+    session: &mut SshSession,
+    user: &str,
+) -> std::result::Result<AuthResult, SshError> {
+    #[cfg(unix)]
+    let agent = AgentClient::connect_env().await;
+
+    #[cfg(windows)]
+    let agent = match std::env::var("SSH_AUTH_SOCK") {
+        Ok(pipe) => AgentClient::connect_named_pipe(pipe).await,
+        Err(_) => AgentClient::connect_named_pipe(r"\\.\pipe\openssh-ssh-agent").await,
+    };
+
+    let mut agent = agent.map_err(|e| SshError::AgentUnavailable(e.to_string()))?;
+    try_agent_identities(session, user, &mut agent).await
+}
+
+async fn try_agent_identities<S>(
+    session: &mut SshSession,
+    user: &str,
+    agent: &mut AgentClient<S>,
+) -> std::result::Result<AuthResult, SshError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let identities = agent
+        .request_identities()
+        .await
+        .map_err(|e| SshError::AgentUnavailable(e.to_string()))?;
+
+    if identities.is_empty() {
+        return Err(SshError::AgentUnavailable("the agent holds no keys".into()));
+    }
+
+    let mut last_res = AuthResult::Failure {
+        remaining_methods: russh::MethodSet::empty(),
+        partial_success: false,
+    };
+
+    for key in identities {
+        last_res = session
+            .authenticate_publickey_with(user, key, None, agent)
+            .await
+            .map_err(|e| SshError::HandshakeFailed(format!("Auth request failed: {}", e)))?;
+
+        if last_res.success() {
+            break;
         }
-        _ => std::result::Result::Err(SshError::AuthFailed("Access denied".into())),
     }
+
+    Ok(last_res)
+}
+
+#[derive(Debug, Clone)]
+pub enum OutputChunk {
+    Stdout(String),
+    Stderr(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CommandLimits {
+    pub timeout: Option<Duration>,
+    pub cancel: Option<CancellationToken>,
 }
 
 pub async fn run_remote_cmd(session: &SshSession, cmd: &str) -> anyhow::Result<(String, i32)> {
+    run_remote_cmd_with(session, cmd, None, &mut |_| {}, &CommandLimits::default()).await
+}
+
+pub async fn run_remote_cmd_streaming(
+    session: &SshSession,
+    cmd: &str,
+    on_output: &mut (dyn FnMut(OutputChunk) + Send),
+) -> anyhow::Result<(String, i32)> {
+    run_remote_cmd_with(session, cmd, None, on_output, &CommandLimits::default()).await
+}
+
+pub async fn run_remote_cmd_with_input(
+    session: &SshSession,
+    cmd: &str,
+    input: &[u8],
+) -> anyhow::Result<(String, i32)> {
+    run_remote_cmd_with(session, cmd, Some(input), &mut |_| {}, &CommandLimits::default()).await
+}
+
+pub async fn run_remote_cmd_with(
+    session: &SshSession,
+    cmd: &str,
+    input: Option<&[u8]>,
+    on_output: &mut (dyn FnMut(OutputChunk) + Send),
+    limits: &CommandLimits,
+) -> anyhow::Result<(String, i32)> {
+    if limits.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+        return Err(SshError::Cancelled.into());
+    }
+
     let mut channel = session
         .channel_open_session()
         .await
@@ -85,16 +667,58 @@ pub async fn run_remote_cmd(session: &SshSession, cmd: &str) -> anyhow::Result<(
 
     channel.exec(true, cmd).await?;
 
+    if let Some(input) = input {
+        channel.data(input).await.context("Failed to stream data to SSH channel")?;
+        channel.eof().await?;
+    }
+
+    let deadline = async {
+        match limits.timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let cancelled = async {
+        match &limits.cancel {
+            Some(cancel) => cancel.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let aborted = tokio::select! {
+        res = collect_output(&mut channel, on_output) => return res,
+        _ = deadline => SshError::CommandTimedOut {
+            command: cmd.to_string(),
+            after: limits.timeout.unwrap_or_default(),
+        },
+        _ = cancelled => SshError::Cancelled,
+    };
+
+    let _ = channel.signal(russh::Sig::TERM).await;
+    let _ = channel.close().await;
+
+    Err(aborted.into())
+}
+
+async fn collect_output(
+    channel: &mut Channel<Msg>,
+    on_output: &mut (dyn FnMut(OutputChunk) + Send),
+) -> anyhow::Result<(String, i32)> {
     let mut output = String::new();
     let mut exit_code = 0;
 
     while let Some(msg) = channel.wait().await {
         match msg {
             russh::ChannelMsg::Data { ref data } => {
-                output.push_str(&String::from_utf8_lossy(data));
+                let chunk = String::from_utf8_lossy(data);
+                output.push_str(&chunk);
+                on_output(OutputChunk::Stdout(chunk.into_owned()));
             }
             russh::ChannelMsg::ExtendedData { ref data, .. } => {
-                output.push_str(&String::from_utf8_lossy(data));
+                let chunk = String::from_utf8_lossy(data);
+                output.push_str(&chunk);
+                on_output(OutputChunk::Stderr(chunk.into_owned()));
             }
             russh::ChannelMsg::ExitStatus { exit_status } => {
                 exit_code = exit_status as i32;
@@ -108,14 +732,80 @@ pub async fn run_remote_cmd(session: &SshSession, cmd: &str) -> anyhow::Result<(
 }
 
 pub async fn harden_ssh(ssh_client: &SshClient) -> anyhow::Result<()> {
-    ssh_client.exec("sed -i 's/^#\\?PasswordAuthentication .*/PasswordAuthentication no/' /etc/ssh/sshd_config").await?;
-    ssh_client.exec("sed -i 's/^#\\?ChallengeResponseAuthentication .*/ChallengeResponseAuthentication no/' /etc/ssh/sshd_config").await?;
+    for setting in ["PasswordAuthentication", "ChallengeResponseAuthentication"] {
+        let sed = RemoteCommand::new("sed").args([
+            "-i".to_string(),
+            format!("s/^#\\?{0} .*/{0} no/", setting),
+            "/etc/ssh/sshd_config".to_string(),
+        ]);
+        ssh_client.run(&sed).await?;
+    }
 
-    let restart_cmd = format!(
-        "(sleep 1 && {} systemctl restart ssh) > /dev/null 2>&1 &",
-        ssh_client.sudo_prefix
+    // Restarting from the background lets this command return before sshd
+    // drops the connection. The daemon is `ssh` on Debian/Ubuntu and `sshd`
+    // everywhere else.
+    let restart = RemoteCommand::shell(
+        "(sleep 1 && (systemctl restart sshd || systemctl restart ssh || rc-service sshd restart)) \
+         > /dev/null 2>&1 &",
     );
-    ssh_client.exec_raw(&restart_cmd).await?;
+    ssh_client.run(&restart).await?;
 
     anyhow::Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_safe_words_unquoted() {
+        assert_eq!(shell_quote("wg0"), "wg0");
+        assert_eq!(
+            shell_quote("/etc/wireguard/wg0.conf"),
+            "/etc/wireguard/wg0.conf"
+        );
+        assert_eq!(shell_quote("--add-port=51820/udp"), "--add-port=51820/udp");
+    }
+
+    #[test]
+    fn quotes_empty_strings_and_spaces() {
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("two words"), "'two words'");
+        assert_eq!(shell_quote("$HOME; rm -rf /"), "'$HOME; rm -rf /'");
+    }
+
+    #[test]
+    fn escapes_embedded_single_quotes() {
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote("'"), "''\\'''");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn shell_reads_quoted_command_back_verbatim() {
+        let args = [
+            "",
+            "two words",
+            "it's",
+            "'quoted'",
+            "$HOME `id` \\n",
+            "tab\there",
+        ];
+        let script = RemoteCommand::new("printf")
+            .arg("%s\\0")
+            .args(args)
+            .to_shell_string();
+
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .output()
+            .unwrap();
+
+        let words: Vec<&str> = std::str::from_utf8(&output.stdout)
+            .unwrap()
+            .split_terminator('\0')
+            .collect();
+        assert_eq!(words, args);
+    }
+}
@@ -2,7 +2,7 @@ use std::{fs, path::Path, process::Command};
 
 use anyhow::Context;
 
-use crate::utils::create_command;
+use crate::{host::Host, utils::create_command};
 
 pub fn list_local_configs(conf_dir: &Path) -> anyhow::Result<Vec<String>> {
     let mut configs = Vec::new();
@@ -23,6 +23,32 @@ pub fn list_local_configs(conf_dir: &Path) -> anyhow::Result<Vec<String>> {
     anyhow::Ok(configs)
 }
 
+/// Interface names are limited to 15 characters, so IPv6 addresses and
+/// long hostnames are cut down and given a stable hash suffix.
+pub fn interface_name(host: &Host) -> String {
+    let sanitized: String = host
+        .to_string()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '=' | '+' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+
+    if sanitized.len() <= 15 && sanitized == host.to_string() {
+        return sanitized;
+    }
+
+    // FNV-1a, chosen because it is stable across builds and platforms.
+    let hash = host
+        .to_string()
+        .bytes()
+        .fold(0x811c9dc5u32, |hash, b| (hash ^ b as u32).wrapping_mul(0x01000193));
+
+    let prefix: String = sanitized.chars().take(6).collect();
+    format!("{}-{:08x}", prefix, hash)
+}
+
 pub fn start_tunnel(conf_path: &Path) -> anyhow::Result<()> {
     let path_str = conf_path
         .to_str()
@@ -75,3 +101,49 @@ pub fn stop_tunnel(conf_name: &str) -> anyhow::Result<()> {
         anyhow::bail!("WireGuard error: {}", err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_for(host: &str) -> String {
+        interface_name(&host.parse().unwrap())
+    }
+
+    #[test]
+    fn keeps_short_names_that_are_already_valid() {
+        assert_eq!(name_for("203.0.113.7"), "203.0.113.7");
+        assert_eq!(name_for("100.100.100.100"), "100.100.100.100");
+        assert_eq!(name_for("vpn.example.com"), "vpn.example.com");
+    }
+
+    #[test]
+    fn shortens_ipv6_addresses_with_a_hash() {
+        assert_eq!(name_for("2001:db8::1"), "2001_d-3aefc9c7");
+        assert_eq!(name_for("[2001:db8::1]"), "2001_d-3aefc9c7");
+    }
+
+    #[test]
+    fn shortens_long_hostnames_with_a_hash() {
+        assert_eq!(name_for("wireguard.example.com"), "wiregu-bc4606ba");
+        assert_eq!(name_for("wireguard.example.net"), "wiregu-59e3352c");
+    }
+
+    #[test]
+    fn always_yields_a_valid_interface_name() {
+        for host in [
+            "203.0.113.7",
+            "2001:db8:85a3::8a2e:370:7334",
+            "::1",
+            "a-very-long-subdomain.of.some.example.com",
+        ] {
+            let name = name_for(host);
+            assert!(name.len() <= 15, "{name} is too long");
+            assert!(
+                name.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_=+.-".contains(c)),
+                "{name} has invalid characters"
+            );
+        }
+    }
+}
@@ -0,0 +1,602 @@
+use std::{
+    fmt::{self, Display},
+    net::IpAddr,
+    str::FromStr,
+};
+
+use base64::{Engine, engine::general_purpose};
+use ipnet::IpNet;
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum ConfigError {
+    #[error("Line {line}: unknown section '{found}', expected [Interface] or [Peer]")]
+    UnknownSection { line: usize, found: String },
+    #[error("Line {line}: '{found}' is not inside a section")]
+    OutsideSection { line: usize, found: String },
+    #[error("Line {line}: expected 'Key = Value'")]
+    MissingValue { line: usize },
+    #[error("Line {line}: unknown key '{key}' in [{section}]")]
+    UnknownKey {
+        line: usize,
+        key: String,
+        section: &'static str,
+    },
+    #[error("Line {line}: invalid value for {key}")]
+    InvalidValue { line: usize, key: &'static str },
+    #[error("Line {line}: only one [Interface] section is allowed")]
+    DuplicateInterface { line: usize },
+    #[error("Line {line}: [Peer] section has no PublicKey")]
+    MissingPublicKey { line: usize },
+    #[error("Config has no [Interface] section")]
+    MissingInterface,
+}
+
+impl ConfigError {
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            ConfigError::UnknownSection { line, .. }
+            | ConfigError::OutsideSection { line, .. }
+            | ConfigError::MissingValue { line }
+            | ConfigError::UnknownKey { line, .. }
+            | ConfigError::InvalidValue { line, .. }
+            | ConfigError::DuplicateInterface { line }
+            | ConfigError::MissingPublicKey { line } => Some(*line),
+            ConfigError::MissingInterface => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WgConfig {
+    pub interface: InterfaceSection,
+    pub peers: Vec<PeerSection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InterfaceSection {
+    pub comments: Vec<String>,
+    pub private_key: Option<String>,
+    pub address: Vec<IpNet>,
+    pub listen_port: Option<u16>,
+    pub fw_mark: Option<String>,
+    pub dns: Vec<IpAddr>,
+    /// Non-IP `DNS` entries, which wg-quick uses as search domains.
+    pub dns_search: Vec<String>,
+    pub mtu: Option<u16>,
+    pub table: Option<String>,
+    pub save_config: Option<bool>,
+    pub pre_up: Vec<String>,
+    pub post_up: Vec<String>,
+    pub pre_down: Vec<String>,
+    pub post_down: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PeerSection {
+    pub comments: Vec<String>,
+    pub public_key: String,
+    pub preshared_key: Option<String>,
+    pub endpoint: Option<String>,
+    pub allowed_ips: Vec<IpNet>,
+    pub persistent_keepalive: Option<u16>,
+}
+
+enum Section {
+    Interface(InterfaceSection),
+    Peer(usize, PeerSection),
+}
+
+impl Section {
+    fn comments_mut(&mut self) -> &mut Vec<String> {
+        match self {
+            Section::Interface(interface) => &mut interface.comments,
+            Section::Peer(_, peer) => &mut peer.comments,
+        }
+    }
+}
+
+impl FromStr for WgConfig {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut interface = None;
+        let mut peers = Vec::new();
+        let mut current: Option<Section> = None;
+        // Comments between two sections belong to the one that follows.
+        let mut pending_comments = Vec::new();
+
+        for (index, raw_line) in s.lines().enumerate() {
+            let line = index + 1;
+            // Like wg-quick, everything after a `#` is a comment.
+            let (content, comment) = match raw_line.find('#') {
+                Some(start) => (&raw_line[..start], Some(raw_line[start..].trim_end())),
+                None => (raw_line, None),
+            };
+            let content = content.trim();
+
+            if content.is_empty() {
+                pending_comments.extend(comment.map(str::to_string));
+                continue;
+            }
+
+            if let Some(name) = content.strip_prefix('[').and_then(|c| c.strip_suffix(']')) {
+                if let Some(section) = current.take() {
+                    finish_section(section, &mut interface, &mut peers)?;
+                }
+
+                let mut section = match name.trim().to_ascii_lowercase().as_str() {
+                    "interface" if interface.is_some() => {
+                        return Err(ConfigError::DuplicateInterface { line });
+                    }
+                    "interface" => Section::Interface(InterfaceSection::default()),
+                    "peer" => Section::Peer(line, PeerSection::default()),
+                    _ => {
+                        return Err(ConfigError::UnknownSection {
+                            line,
+                            found: content.to_string(),
+                        });
+                    }
+                };
+                section.comments_mut().append(&mut pending_comments);
+                section.comments_mut().extend(comment.map(str::to_string));
+                current = Some(section);
+                continue;
+            }
+
+            let Some(section) = current.as_mut() else {
+                return Err(ConfigError::OutsideSection {
+                    line,
+                    found: content.to_string(),
+                });
+            };
+            let Some((key, value)) = content.split_once('=') else {
+                return Err(ConfigError::MissingValue { line });
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            match section {
+                Section::Interface(interface) => interface.set(key, value, line)?,
+                Section::Peer(_, peer) => peer.set(key, value, line)?,
+            }
+            section.comments_mut().append(&mut pending_comments);
+            section.comments_mut().extend(comment.map(str::to_string));
+        }
+
+        if let Some(mut section) = current {
+            section.comments_mut().append(&mut pending_comments);
+            finish_section(section, &mut interface, &mut peers)?;
+        }
+
+        Ok(WgConfig {
+            interface: interface.ok_or(ConfigError::MissingInterface)?,
+            peers,
+        })
+    }
+}
+
+fn finish_section(
+    section: Section,
+    interface: &mut Option<InterfaceSection>,
+    peers: &mut Vec<PeerSection>,
+) -> Result<(), ConfigError> {
+    match section {
+        Section::Interface(section) => *interface = Some(section),
+        Section::Peer(line, peer) => {
+            if peer.public_key.is_empty() {
+                return Err(ConfigError::MissingPublicKey { line });
+            }
+            peers.push(peer);
+        }
+    }
+
+    Ok(())
+}
+
+impl InterfaceSection {
+    fn set(&mut self, key: &str, value: &str, line: usize) -> Result<(), ConfigError> {
+        match key.to_ascii_lowercase().as_str() {
+            "privatekey" => self.private_key = Some(parse_key(value, "PrivateKey", line)?),
+            "address" => self
+                .address
+                .extend(parse_list(value, "Address", line, parse_address)?),
+            "listenport" => self.listen_port = Some(parse_value(value, "ListenPort", line)?),
+            "fwmark" => self.fw_mark = Some(value.to_string()),
+            "dns" => {
+                for entry in parse_list(value, "DNS", line, |entry| Some(entry.to_string()))? {
+                    match entry.parse() {
+                        Ok(ip) => self.dns.push(ip),
+                        Err(_) => self.dns_search.push(entry),
+                    }
+                }
+            }
+            "mtu" => self.mtu = Some(parse_value(value, "MTU", line)?),
+            "table" => self.table = Some(value.to_string()),
+            "saveconfig" => {
+                self.save_config = Some(match value.to_ascii_lowercase().as_str() {
+                    "true" => true,
+                    "false" => false,
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            line,
+                            key: "SaveConfig",
+                        });
+                    }
+                })
+            }
+            "preup" => self.pre_up.push(value.to_string()),
+            "postup" => self.post_up.push(value.to_string()),
+            "predown" => self.pre_down.push(value.to_string()),
+            "postdown" => self.post_down.push(value.to_string()),
+            _ => {
+                return Err(ConfigError::UnknownKey {
+                    line,
+                    key: key.to_string(),
+                    section: "Interface",
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl PeerSection {
+    fn set(&mut self, key: &str, value: &str, line: usize) -> Result<(), ConfigError> {
+        match key.to_ascii_lowercase().as_str() {
+            "publickey" => self.public_key = parse_key(value, "PublicKey", line)?,
+            "presharedkey" => self.preshared_key = Some(parse_key(value, "PresharedKey", line)?),
+            "endpoint" => {
+                let valid_port = value
+                    .rsplit_once(':')
+                    .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+                if !valid_port {
+                    return Err(ConfigError::InvalidValue {
+                        line,
+                        key: "Endpoint",
+                    });
+                }
+                self.endpoint = Some(value.to_string());
+            }
+            "allowedips" => {
+                self.allowed_ips
+                    .extend(parse_list(value, "AllowedIPs", line, parse_address)?)
+            }
+            "persistentkeepalive" => {
+                self.persistent_keepalive = match value {
+                    "off" => None,
+                    _ => Some(parse_value(value, "PersistentKeepalive", line)?),
+                }
+            }
+            _ => {
+                return Err(ConfigError::UnknownKey {
+                    line,
+                    key: key.to_string(),
+                    section: "Peer",
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_key(value: &str, key: &'static str, line: usize) -> Result<String, ConfigError> {
+    match general_purpose::STANDARD.decode(value) {
+        Ok(bytes) if bytes.len() == 32 => Ok(value.to_string()),
+        _ => Err(ConfigError::InvalidValue { line, key }),
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str, key: &'static str, line: usize) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::InvalidValue { line, key })
+}
+
+fn parse_list<T>(
+    value: &str,
+    key: &'static str,
+    line: usize,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, ConfigError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| parse(entry).ok_or(ConfigError::InvalidValue { line, key }))
+        .collect()
+}
+
+fn parse_address(value: &str) -> Option<IpNet> {
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+fn write_list<T: Display>(f: &mut fmt::Formatter<'_>, key: &str, entries: &[T]) -> fmt::Result {
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let entries: Vec<String> = entries.iter().map(T::to_string).collect();
+    writeln!(f, "{} = {}", key, entries.join(", "))
+}
+
+fn write_option<T: Display>(
+    f: &mut fmt::Formatter<'_>,
+    key: &str,
+    value: &Option<T>,
+) -> fmt::Result {
+    match value {
+        Some(value) => writeln!(f, "{} = {}", key, value),
+        None => Ok(()),
+    }
+}
+
+fn write_each(f: &mut fmt::Formatter<'_>, key: &str, values: &[String]) -> fmt::Result {
+    values
+        .iter()
+        .try_for_each(|value| writeln!(f, "{} = {}", key, value))
+}
+
+impl Display for WgConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.interface)?;
+        for peer in &self.peers {
+            write!(f, "\n{}", peer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for InterfaceSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.comments
+            .iter()
+            .try_for_each(|comment| writeln!(f, "{}", comment))?;
+        writeln!(f, "[Interface]")?;
+        write_option(f, "PrivateKey", &self.private_key)?;
+        write_list(f, "Address", &self.address)?;
+        write_option(f, "ListenPort", &self.listen_port)?;
+        write_option(f, "FwMark", &self.fw_mark)?;
+
+        let dns: Vec<String> = self
+            .dns
+            .iter()
+            .map(IpAddr::to_string)
+            .chain(self.dns_search.iter().cloned())
+            .collect();
+        write_list(f, "DNS", &dns)?;
+
+        write_option(f, "MTU", &self.mtu)?;
+        write_option(f, "Table", &self.table)?;
+        write_option(f, "SaveConfig", &self.save_config)?;
+        write_each(f, "PreUp", &self.pre_up)?;
+        write_each(f, "PostUp", &self.post_up)?;
+        write_each(f, "PreDown", &self.pre_down)?;
+        write_each(f, "PostDown", &self.post_down)
+    }
+}
+
+impl Display for PeerSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.comments
+            .iter()
+            .try_for_each(|comment| writeln!(f, "{}", comment))?;
+        writeln!(f, "[Peer]")?;
+        writeln!(f, "PublicKey = {}", self.public_key)?;
+        write_option(f, "PresharedKey", &self.preshared_key)?;
+        write_option(f, "Endpoint", &self.endpoint)?;
+        write_list(f, "AllowedIPs", &self.allowed_ips)?;
+        write_option(f, "PersistentKeepalive", &self.persistent_keepalive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER_KEY: &str = "yAnz5TF+lXXJte14tji3zlMNq+hd2rYUIgJBgB3fBmk=";
+    const PEER_KEY: &str = "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=";
+    const OTHER_PEER_KEY: &str = "TrMvSoP4jYQlY6RIzBgbssQqY3vxI2Pi+y71lOWWXX0=";
+
+    fn net(value: &str) -> IpNet {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn round_trips_a_full_config() {
+        let text = format!(
+            "# Server
+[Interface]
+PrivateKey = {SERVER_KEY}
+Address = 10.8.0.1/24, fd00::1/64
+ListenPort = 51820
+FwMark = 0x1234
+DNS = 1.1.1.1, 2606:4700:4700::1111, lan
+MTU = 1420
+Table = off
+SaveConfig = false
+PreUp = echo pre-up
+PostUp = iptables -A FORWARD -i %i -j ACCEPT
+PostUp = sysctl -w net.ipv4.ip_forward=1
+PreDown = echo pre-down
+PostDown = iptables -D FORWARD -i %i -j ACCEPT
+
+# Peer: laptop
+[Peer]
+PublicKey = {PEER_KEY}
+PresharedKey = {OTHER_PEER_KEY}
+Endpoint = vpn.example.com:51820
+AllowedIPs = 10.8.0.2/32, fd00::2/128
+PersistentKeepalive = 25
+"
+        );
+
+        let config: WgConfig = text.parse().unwrap();
+        assert_eq!(config.to_string(), text);
+        assert_eq!(config.to_string().parse::<WgConfig>().unwrap(), config);
+    }
+
+    #[test]
+    fn parses_multiple_peers_in_order() {
+        let text = format!(
+            "[Interface]
+PrivateKey = {SERVER_KEY}
+
+[Peer]
+PublicKey = {PEER_KEY}
+AllowedIPs = 10.8.0.2/32
+
+[Peer]
+PublicKey = {OTHER_PEER_KEY}
+AllowedIPs = 10.8.0.3/32
+"
+        );
+
+        let config: WgConfig = text.parse().unwrap();
+        let keys: Vec<&str> = config.peers.iter().map(|p| p.public_key.as_str()).collect();
+        assert_eq!(keys, [PEER_KEY, OTHER_PEER_KEY]);
+        assert_eq!(config.peers[1].allowed_ips, [net("10.8.0.3/32")]);
+    }
+
+    #[test]
+    fn keeps_comments_with_their_section_and_drops_blank_lines() {
+        let text = format!(
+            "# Interface comment
+
+[Interface]   # on the header
+PrivateKey = {SERVER_KEY}
+
+
+Address = 10.8.0.1/24 # inline
+
+# Belongs to the peer
+[Peer]
+PublicKey = {PEER_KEY}
+# trailing
+"
+        );
+
+        let config: WgConfig = text.parse().unwrap();
+        assert_eq!(
+            config.interface.comments,
+            ["# Interface comment", "# on the header", "# inline"]
+        );
+        assert_eq!(config.interface.address, [net("10.8.0.1/24")]);
+        assert_eq!(
+            config.peers[0].comments,
+            ["# Belongs to the peer", "# trailing"]
+        );
+        assert_eq!(
+            config.to_string(),
+            format!(
+                "# Interface comment
+# on the header
+# inline
+[Interface]
+PrivateKey = {SERVER_KEY}
+Address = 10.8.0.1/24
+
+# Belongs to the peer
+# trailing
+[Peer]
+PublicKey = {PEER_KEY}
+"
+            )
+        );
+    }
+
+    #[test]
+    fn reads_keys_case_insensitively_and_bare_addresses_as_hosts() {
+        let text = format!(
+            "[interface]
+privatekey = {SERVER_KEY}
+ADDRESS = 10.8.0.1, fd00::1
+dns = 10.8.0.1, corp.example.com
+[PEER]
+publickey = {PEER_KEY}
+persistentkeepalive = off
+"
+        );
+
+        let config: WgConfig = text.parse().unwrap();
+        assert_eq!(
+            config.interface.address,
+            [net("10.8.0.1/32"), net("fd00::1/128")]
+        );
+        assert_eq!(
+            config.interface.dns,
+            ["10.8.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(config.interface.dns_search, ["corp.example.com"]);
+        assert_eq!(config.peers[0].persistent_keepalive, None);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let text = format!("[Interface]\nPrivateKey = {SERVER_KEY}\nColour = blue\n");
+
+        assert_eq!(
+            text.parse::<WgConfig>(),
+            Err(ConfigError::UnknownKey {
+                line: 3,
+                key: "Colour".to_string(),
+                section: "Interface",
+            })
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_value() {
+        let text = format!(
+            "[Interface]
+PrivateKey = {SERVER_KEY}
+
+[Peer]
+PublicKey = {PEER_KEY}
+# comment
+AllowedIPs = 10.8.0.2/32, not-a-network
+"
+        );
+
+        let err = text.parse::<WgConfig>().unwrap_err();
+        assert_eq!(
+            err,
+            ConfigError::InvalidValue {
+                line: 7,
+                key: "AllowedIPs"
+            }
+        );
+        assert_eq!(err.line(), Some(7));
+    }
+
+    #[test]
+    fn rejects_malformed_structure() {
+        let parse = |text: &str| text.parse::<WgConfig>().unwrap_err();
+
+        assert_eq!(
+            parse("PrivateKey = x\n"),
+            ConfigError::OutsideSection {
+                line: 1,
+                found: "PrivateKey = x".to_string(),
+            }
+        );
+        assert_eq!(
+            parse("[Interface]\n[Interface]\n"),
+            ConfigError::DuplicateInterface { line: 2 }
+        );
+        assert_eq!(
+            parse("[Interface]\n[Peer]\nEndpoint = a:1\n"),
+            ConfigError::MissingPublicKey { line: 2 }
+        );
+        assert_eq!(
+            parse("[Interface]\nMTU\n"),
+            ConfigError::MissingValue { line: 2 }
+        );
+        assert_eq!(parse("[Peer]\n"), ConfigError::MissingPublicKey { line: 1 });
+        assert_eq!(parse(""), ConfigError::MissingInterface);
+    }
+}
@@ -0,0 +1,26 @@
+use std::io::Cursor;
+
+use image::{ImageFormat, Luma};
+use qrcode::QrCode;
+
+pub const PRIVATE_KEY_WARNING: &str = "\
+# WARNING: this file contains the private key of this tunnel.
+# Anyone who has it can connect as this device. Keep it private and
+# delete it once it is imported.";
+
+pub fn with_private_key_warning(config: &str) -> String {
+    format!("{}\n{}", PRIVATE_KEY_WARNING, config)
+}
+
+pub fn config_qr_png(config: &str) -> anyhow::Result<Vec<u8>> {
+    let code = QrCode::new(config.as_bytes())?;
+    let image = code
+        .render::<Luma<u8>>()
+        .min_dimensions(512, 512)
+        .build();
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+    Ok(png)
+}
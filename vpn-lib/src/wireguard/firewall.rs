@@ -0,0 +1,353 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ssh::{RemoteCommand, SshClient},
+    wireguard::{config::InterfaceSection, settings::ServerSettings},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FirewallBackend {
+    #[default]
+    Iptables,
+    Nftables,
+}
+
+impl FirewallBackend {
+    pub fn add_hooks(
+        &self,
+        interface: &mut InterfaceSection,
+        settings: &ServerSettings,
+        egress_interface: &str,
+    ) {
+        match self {
+            FirewallBackend::Iptables => iptables_hooks(interface, settings, egress_interface),
+            FirewallBackend::Nftables => nftables_hooks(interface, settings, egress_interface),
+        }
+    }
+}
+
+fn iptables_hooks(interface: &mut InterfaceSection, settings: &ServerSettings, egress_interface: &str) {
+    interface.post_up.push(format!(
+        "sysctl -w net.ipv4.ip_forward=1; iptables -A FORWARD -i %i -j ACCEPT; iptables -t nat -A POSTROUTING -o {egress_interface} -j MASQUERADE"
+    ));
+    interface.post_down.push(format!(
+        "iptables -D FORWARD -i %i -j ACCEPT; iptables -t nat -D POSTROUTING -o {egress_interface} -j MASQUERADE"
+    ));
+
+    match settings.subnet_v6 {
+        Some(_) => {
+            interface.post_up.push(format!(
+                "sysctl -w net.ipv6.conf.all.forwarding=1; ip6tables -A FORWARD -i %i -j ACCEPT; ip6tables -t nat -A POSTROUTING -o {egress_interface} -j MASQUERADE"
+            ));
+            interface.post_down.push(format!(
+                "ip6tables -D FORWARD -i %i -j ACCEPT; ip6tables -t nat -D POSTROUTING -o {egress_interface} -j MASQUERADE"
+            ));
+        }
+        None => {
+            interface
+                .post_up
+                .push("ip6tables -A FORWARD -i %i -j REJECT".to_string());
+            interface
+                .post_down
+                .push("ip6tables -D FORWARD -i %i -j REJECT".to_string());
+        }
+    }
+}
+
+/// Everything lives in a dedicated `inet` table, so `wg-quick down` only has
+/// to delete that table and never touches rules owned by anything else.
+fn nftables_hooks(interface: &mut InterfaceSection, settings: &ServerSettings, egress_interface: &str) {
+    let table = nft_table_name(&settings.interface);
+    let subnet = settings.subnet.trunc();
+
+    let mut rules = vec!["sysctl -w net.ipv4.ip_forward=1".to_string()];
+    if settings.subnet_v6.is_some() {
+        rules.push("sysctl -w net.ipv6.conf.all.forwarding=1".to_string());
+    }
+
+    // Flushing makes a second `up` after an unclean shutdown start from an
+    // empty table instead of stacking duplicate rules.
+    rules.push(format!("nft add table inet {table}"));
+    rules.push(format!("nft flush table inet {table}"));
+    rules.push(format!(
+        "nft add chain inet {table} forward '{{ type filter hook forward priority 0; policy accept; }}'"
+    ));
+    rules.push(format!(
+        "nft add chain inet {table} postrouting '{{ type nat hook postrouting priority 100; }}'"
+    ));
+
+    if settings.subnet_v6.is_none() {
+        rules.push(format!(
+            "nft add rule inet {table} forward iifname \"%i\" meta nfproto ipv6 reject"
+        ));
+    }
+    rules.push(format!("nft add rule inet {table} forward iifname \"%i\" accept"));
+    rules.push(format!(
+        "nft add rule inet {table} forward oifname \"%i\" ct state related,established accept"
+    ));
+
+    rules.push(format!(
+        "nft add rule inet {table} postrouting ip saddr {subnet} oifname \"{egress_interface}\" masquerade"
+    ));
+    if let Some(subnet_v6) = settings.subnet_v6 {
+        rules.push(format!(
+            "nft add rule inet {table} postrouting ip6 saddr {} oifname \"{egress_interface}\" masquerade",
+            subnet_v6.trunc()
+        ));
+    }
+
+    interface.post_up.push(rules.join("; "));
+    interface
+        .post_down
+        .push(format!("nft delete table inet {table}"));
+}
+
+fn nft_table_name(interface: &str) -> String {
+    let sanitized: String = interface
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("wireguard_{}", sanitized)
+}
+
+/// nftables wins when `iptables` is missing or only its nf_tables wrapper.
+/// Probes go through sudo, whose PATH includes /usr/sbin on Debian.
+pub async fn detect_firewall_backend(ssh_client: &SshClient) -> anyhow::Result<FirewallBackend> {
+    let (_, has_nft) = ssh_client
+        .run(&RemoteCommand::shell("command -v nft"))
+        .await?;
+    let (iptables_version, has_iptables) = ssh_client
+        .run(&RemoteCommand::new("iptables").arg("-V"))
+        .await?;
+
+    match (has_nft == 0, has_iptables == 0) {
+        (true, false) => Ok(FirewallBackend::Nftables),
+        (true, true) if iptables_version.contains("nf_tables") => Ok(FirewallBackend::Nftables),
+        _ => Ok(FirewallBackend::Iptables),
+    }
+}
+
+/// Only touched when already active; teardown removes what setup added.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HostFirewall {
+    Ufw,
+    Firewalld,
+}
+
+impl HostFirewall {
+    fn name(&self) -> &'static str {
+        match self {
+            HostFirewall::Ufw => "ufw",
+            HostFirewall::Firewalld => "firewalld",
+        }
+    }
+
+    fn allow_commands(&self, settings: &ServerSettings) -> Vec<RemoteCommand> {
+        let port = format!("{}/udp", settings.listen_port);
+        let interface = settings.interface.as_str();
+
+        match self {
+            HostFirewall::Ufw => ufw_rules(&port, interface)
+                .into_iter()
+                .map(|rule| RemoteCommand::new("ufw").args(rule))
+                .collect(),
+            HostFirewall::Firewalld => vec![
+                RemoteCommand::new("firewall-cmd")
+                    .args(["--permanent".to_string(), format!("--add-port={}", port)]),
+                RemoteCommand::new("firewall-cmd").args([
+                    "--permanent".to_string(),
+                    "--zone=trusted".to_string(),
+                    format!("--add-interface={}", interface),
+                ]),
+                RemoteCommand::new("firewall-cmd").arg("--reload"),
+            ],
+        }
+    }
+
+    fn remove_commands(&self, settings: &ServerSettings) -> Vec<RemoteCommand> {
+        let port = format!("{}/udp", settings.listen_port);
+        let interface = settings.interface.as_str();
+
+        match self {
+            HostFirewall::Ufw => ufw_rules(&port, interface)
+                .into_iter()
+                .map(|rule| RemoteCommand::new("ufw").arg("delete").args(rule))
+                .collect(),
+            HostFirewall::Firewalld => vec![
+                RemoteCommand::new("firewall-cmd")
+                    .args(["--permanent".to_string(), format!("--remove-port={}", port)]),
+                RemoteCommand::new("firewall-cmd").args([
+                    "--permanent".to_string(),
+                    "--zone=trusted".to_string(),
+                    format!("--remove-interface={}", interface),
+                ]),
+                RemoteCommand::new("firewall-cmd").arg("--reload"),
+            ],
+        }
+    }
+}
+
+fn ufw_rules(port: &str, interface: &str) -> Vec<Vec<String>> {
+    [
+        vec!["allow", port],
+        vec!["allow", "in", "on", interface],
+        vec!["route", "allow", "in", "on", interface],
+    ]
+    .into_iter()
+    .map(|rule| rule.into_iter().map(str::to_string).collect())
+    .collect()
+}
+
+pub async fn detect_host_firewall(ssh_client: &SshClient) -> anyhow::Result<Option<HostFirewall>> {
+    let (output, status) = ssh_client
+        .run(&RemoteCommand::new("ufw").arg("status"))
+        .await?;
+    if status == 0 && output.contains("Status: active") {
+        return Ok(Some(HostFirewall::Ufw));
+    }
+
+    let (_, status) = ssh_client
+        .run(&RemoteCommand::new("firewall-cmd").arg("--state"))
+        .await?;
+    if status == 0 {
+        return Ok(Some(HostFirewall::Firewalld));
+    }
+
+    Ok(None)
+}
+
+pub async fn open_host_firewall(
+    ssh_client: &SshClient,
+    host_firewall: HostFirewall,
+    settings: &ServerSettings,
+) -> anyhow::Result<()> {
+    for cmd in host_firewall.allow_commands(settings) {
+        let (output, status) = ssh_client.run(&cmd).await?;
+        if status != 0 {
+            anyhow::bail!(
+                "Failed to open the WireGuard port in {}: {}",
+                host_firewall.name(),
+                output.trim()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn close_host_firewall(
+    ssh_client: &SshClient,
+    host_firewall: HostFirewall,
+    settings: &ServerSettings,
+) -> anyhow::Result<()> {
+    for cmd in host_firewall.remove_commands(settings) {
+        ssh_client.run(&cmd).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hooks(backend: FirewallBackend, settings: &ServerSettings) -> (Vec<String>, Vec<String>) {
+        let mut interface = InterfaceSection::default();
+        backend.add_hooks(&mut interface, settings, "eth0");
+        (interface.post_up, interface.post_down)
+    }
+
+    fn dual_stack() -> ServerSettings {
+        ServerSettings {
+            subnet: "10.8.0.1/24".parse().unwrap(),
+            subnet_v6: Some("fd00:1234:5678::1/64".parse().unwrap()),
+            interface: "wg-home.1".to_string(),
+            ..ServerSettings::default()
+        }
+    }
+
+    #[test]
+    fn iptables_rejects_ipv6_without_a_v6_subnet() {
+        let (post_up, post_down) = hooks(FirewallBackend::Iptables, &ServerSettings::default());
+
+        assert_eq!(
+            post_up,
+            [
+                "sysctl -w net.ipv4.ip_forward=1; iptables -A FORWARD -i %i -j ACCEPT; iptables -t nat -A POSTROUTING -o eth0 -j MASQUERADE",
+                "ip6tables -A FORWARD -i %i -j REJECT",
+            ]
+        );
+        assert_eq!(
+            post_down,
+            [
+                "iptables -D FORWARD -i %i -j ACCEPT; iptables -t nat -D POSTROUTING -o eth0 -j MASQUERADE",
+                "ip6tables -D FORWARD -i %i -j REJECT",
+            ]
+        );
+    }
+
+    #[test]
+    fn iptables_nats_ipv6_when_dual_stack() {
+        let (post_up, post_down) = hooks(FirewallBackend::Iptables, &dual_stack());
+
+        assert_eq!(
+            post_up[1],
+            "sysctl -w net.ipv6.conf.all.forwarding=1; ip6tables -A FORWARD -i %i -j ACCEPT; ip6tables -t nat -A POSTROUTING -o eth0 -j MASQUERADE"
+        );
+        assert_eq!(
+            post_down[1],
+            "ip6tables -D FORWARD -i %i -j ACCEPT; ip6tables -t nat -D POSTROUTING -o eth0 -j MASQUERADE"
+        );
+        assert_eq!(post_up.len(), 2);
+        assert_eq!(post_down.len(), 2);
+    }
+
+    #[test]
+    fn nftables_rejects_ipv6_without_a_v6_subnet() {
+        let (post_up, post_down) = hooks(FirewallBackend::Nftables, &ServerSettings::default());
+
+        let rules = [
+            "sysctl -w net.ipv4.ip_forward=1",
+            "nft add table inet wireguard_wg0",
+            "nft flush table inet wireguard_wg0",
+            "nft add chain inet wireguard_wg0 forward '{ type filter hook forward priority 0; policy accept; }'",
+            "nft add chain inet wireguard_wg0 postrouting '{ type nat hook postrouting priority 100; }'",
+            "nft add rule inet wireguard_wg0 forward iifname \"%i\" meta nfproto ipv6 reject",
+            "nft add rule inet wireguard_wg0 forward iifname \"%i\" accept",
+            "nft add rule inet wireguard_wg0 forward oifname \"%i\" ct state related,established accept",
+            "nft add rule inet wireguard_wg0 postrouting ip saddr 10.0.0.0/24 oifname \"eth0\" masquerade",
+        ];
+        assert_eq!(post_up, [rules.join("; ")]);
+        assert_eq!(post_down, ["nft delete table inet wireguard_wg0"]);
+    }
+
+    #[test]
+    fn nftables_nats_ipv6_when_dual_stack() {
+        let (post_up, post_down) = hooks(FirewallBackend::Nftables, &dual_stack());
+
+        let rules = [
+            "sysctl -w net.ipv4.ip_forward=1",
+            "sysctl -w net.ipv6.conf.all.forwarding=1",
+            "nft add table inet wireguard_wg_home_1",
+            "nft flush table inet wireguard_wg_home_1",
+            "nft add chain inet wireguard_wg_home_1 forward '{ type filter hook forward priority 0; policy accept; }'",
+            "nft add chain inet wireguard_wg_home_1 postrouting '{ type nat hook postrouting priority 100; }'",
+            "nft add rule inet wireguard_wg_home_1 forward iifname \"%i\" accept",
+            "nft add rule inet wireguard_wg_home_1 forward oifname \"%i\" ct state related,established accept",
+            "nft add rule inet wireguard_wg_home_1 postrouting ip saddr 10.8.0.0/24 oifname \"eth0\" masquerade",
+            "nft add rule inet wireguard_wg_home_1 postrouting ip6 saddr fd00:1234:5678::/64 oifname \"eth0\" masquerade",
+        ];
+        assert_eq!(post_up, [rules.join("; ")]);
+        assert_eq!(post_down, ["nft delete table inet wireguard_wg_home_1"]);
+    }
+
+    #[test]
+    fn nft_table_names_only_use_identifier_characters() {
+        assert_eq!(nft_table_name("wg0"), "wireguard_wg0");
+        assert_eq!(nft_table_name("wg-home.1"), "wireguard_wg_home_1");
+        assert_eq!(nft_table_name("wg=+_x"), "wireguard_wg___x");
+    }
+}
@@ -1,5 +1,13 @@
+pub mod config;
+pub mod export;
 pub mod peer;
 pub mod server;
+pub mod provision;
 pub mod state;
+pub mod stats;
+pub mod settings;
+pub mod firewall;
+pub mod routes;
+pub mod resolver;
 pub mod client;
 pub mod interface;
\ No newline at end of file
@@ -1,14 +1,20 @@
 use chrono::{DateTime, Utc};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
-use std::fmt::{self};
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
+use ipnet::IpNet;
+
+use crate::host::Host;
 use crate::ssh::{SshClient, SshSession};
-use crate::wireguard::server::{TunnelMode, build_client_config, update_wireguard_config};
+use crate::wireguard::config::PeerSection;
+use crate::wireguard::routes::{RouteProfile, allowed_ips};
+use crate::wireguard::server::{
+    TunnelMode, build_client_config, set_preshared_key, update_wireguard_config,
+};
 use crate::wireguard::{
-    server::generate_keys,
-    state::{get_or_create_state, save_state},
+    server::{generate_keys, generate_preshared_key},
+    state::{StateError, VpnState, get_or_create_state, save_state},
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -16,51 +22,146 @@ pub struct Peer {
     pub name: String,
     pub public_key: String,
     pub ip: Ipv4Addr,
+    #[serde(default)]
+    pub ipv6: Option<Ipv6Addr>,
+    #[serde(default)]
+    pub has_preshared_key: bool,
     pub crated_at: DateTime<Utc>,
 }
 
+pub struct PeerKeys {
+    pub private_key: SecretString,
+    pub preshared_key: Option<SecretString>,
+}
+
 impl Peer {
-    pub fn new(name: String, ip: Ipv4Addr) -> (Self, SecretString) {
+    pub fn new(
+        name: String,
+        ip: Ipv4Addr,
+        ipv6: Option<Ipv6Addr>,
+        with_preshared_key: bool,
+    ) -> (Self, PeerKeys) {
         let (priv_key, pub_key) = generate_keys();
+        let preshared_key = with_preshared_key.then(generate_preshared_key);
+
         (
             Self {
                 name,
                 public_key: pub_key,
                 ip: ip,
+                ipv6,
+                has_preshared_key: preshared_key.is_some(),
                 crated_at: Utc::now(),
             },
-            priv_key,
+            PeerKeys {
+                private_key: priv_key,
+                preshared_key,
+            },
         )
     }
-}
 
-impl fmt::Display for Peer {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "# Peer: {}\n[Peer]\nPublicKey = {}\nAllowedIPs = {}/32\n",
-            self.name, self.public_key, self.ip
-        )
+    /// No spaces, so the same string works in a config and for `wg set`.
+    pub fn allowed_ips(&self) -> String {
+        match self.ipv6 {
+            Some(ipv6) => format!("{}/32,{}/128", self.ip, ipv6),
+            None => format!("{}/32", self.ip),
+        }
+    }
+
+    pub fn section(&self, preshared_key: Option<&str>) -> PeerSection {
+        let allowed_ips = std::iter::once(IpAddr::V4(self.ip))
+            .chain(self.ipv6.map(IpAddr::V6))
+            .map(IpNet::from)
+            .collect();
+
+        PeerSection {
+            comments: vec![format!("# Peer: {}", self.name)],
+            public_key: self.public_key.clone(),
+            preshared_key: preshared_key.map(str::to_string),
+            allowed_ips,
+            ..PeerSection::default()
+        }
     }
 }
 
 pub async fn add_new_peer(
     ssh_client: &SshClient,
-    server_ip: Ipv4Addr,
+    server_host: &Host,
     name: String,
 ) -> anyhow::Result<String> {
-    let mut state = get_or_create_state(ssh_client, server_ip).await?;
+    let mut state = get_or_create_state(ssh_client, server_host).await?;
+    let (new_peer, keys) = add_peer(ssh_client, &mut state, name).await?;
+
+    let client_config = build_client_config(
+        &keys.private_key.expose_secret(),
+        keys.preshared_key.as_ref().map(|key| key.expose_secret()),
+        &state.server_public_key,
+        &state.server_host,
+        new_peer.ip,
+        new_peer.ipv6,
+        &state.settings,
+        &allowed_ips(TunnelMode::Full, &RouteProfile::default(), &state.settings, None),
+        &state.settings.client_settings(),
+    );
 
+    anyhow::Ok(client_config)
+}
+
+pub async fn add_peer(
+    ssh_client: &SshClient,
+    state: &mut VpnState,
+    name: String,
+) -> anyhow::Result<(Peer, PeerKeys)> {
     let next_ip = state.get_next_available_ip()?;
-    let (new_peer, priv_key) = Peer::new(name, next_ip);
+    let next_ipv6 = state.settings.ipv6_for(next_ip);
+    let (new_peer, keys) = Peer::new(name, next_ip, next_ipv6, state.settings.preshared_keys);
+
+    if let Some(preshared_key) = &keys.preshared_key {
+        set_preshared_key(
+            ssh_client,
+            &state.settings.interface,
+            &new_peer.public_key,
+            preshared_key,
+        )
+        .await?;
+    }
 
-    state.peers.push(new_peer);
+    state.peers.push(new_peer.clone());
+    state.last_updated = Utc::now();
+
+    save_state(ssh_client, state).await?;
+    update_wireguard_config(ssh_client, state).await?;
+
+    anyhow::Ok((new_peer, keys))
+}
+
+pub async fn enable_preshared_key(
+    ssh_client: &SshClient,
+    server_host: &Host,
+    public_key: &str,
+) -> anyhow::Result<SecretString> {
+    let mut state = get_or_create_state(ssh_client, server_host).await?;
+
+    let peer = state
+        .peers
+        .iter_mut()
+        .find(|p| p.public_key == public_key)
+        .ok_or_else(|| StateError::PeerNotFound(public_key.to_string()))?;
+
+    let preshared_key = generate_preshared_key();
+    set_preshared_key(
+        ssh_client,
+        &state.settings.interface,
+        public_key,
+        &preshared_key,
+    )
+    .await?;
+
+    peer.has_preshared_key = true;
     state.last_updated = Utc::now();
 
     save_state(ssh_client, &state).await?;
     update_wireguard_config(ssh_client, &state).await?;
 
-    let client_config = build_client_config(&priv_key.expose_secret(), &state.server_public_key, state.server_ip, next_ip, &TunnelMode::Full);
-
-    anyhow::Ok(client_config)
+    anyhow::Ok(preshared_key)
 }
@@ -0,0 +1,450 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    host::Host,
+    ssh::{RemoteCommand, SshClient},
+    wireguard::{
+        server::{RemoteOs, ServerError, detect_remote_os, resolve_settings, wireguard_installed},
+        settings::ServerSettings,
+        state::{VpnState, get_or_create_state, load_state},
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ExistingInstall {
+    #[default]
+    Abort,
+    Join,
+    Reinstall,
+}
+
+#[derive(Debug)]
+pub enum Installation {
+    None,
+    Managed(Box<VpnState>),
+    Unmanaged {
+        interface: String,
+    },
+}
+
+impl Installation {
+    pub fn interface(&self) -> Option<&str> {
+        match self {
+            Installation::None => None,
+            Installation::Managed(state) => Some(&state.settings.interface),
+            Installation::Unmanaged { interface } => Some(interface),
+        }
+    }
+
+    pub fn summary(&self) -> Option<InstallationSummary> {
+        let interface = self.interface()?.to_string();
+
+        Some(match self {
+            Installation::Managed(state) => InstallationSummary {
+                interface,
+                managed: true,
+                peers: state.peers.iter().map(|p| p.name.clone()).collect(),
+            },
+            _ => InstallationSummary {
+                interface,
+                managed: false,
+                peers: Vec::new(),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct InstallationSummary {
+    pub interface: String,
+    pub managed: bool,
+    pub peers: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SetupPlan {
+    pub os: String,
+    pub existing: Option<InstallationSummary>,
+    pub changes: Vec<String>,
+}
+
+pub async fn detect_installation(
+    ssh_client: &SshClient,
+    host: &Host,
+    settings: &ServerSettings,
+) -> anyhow::Result<Installation> {
+    if let Some(state) = load_state(ssh_client).await? {
+        return Ok(Installation::Managed(Box::new(state)));
+    }
+
+    let config_path = settings.config_path().display().to_string();
+    let (_, status) = ssh_client
+        .run(&RemoteCommand::new("test").args(["-e", config_path.as_str()]))
+        .await?;
+
+    if status != 0 {
+        return Ok(Installation::None);
+    }
+
+    // Servers from before peers.json can still be joined while their
+    // interface is up; anything else is not ours to manage.
+    match get_or_create_state(ssh_client, host).await {
+        Ok(state) if state.settings.interface == settings.interface => {
+            Ok(Installation::Managed(Box::new(state)))
+        }
+        _ => Ok(Installation::Unmanaged {
+            interface: settings.interface.clone(),
+        }),
+    }
+}
+
+pub async fn plan_setup(
+    ssh_client: &SshClient,
+    host: &Host,
+    settings: &ServerSettings,
+    existing: ExistingInstall,
+) -> anyhow::Result<SetupPlan> {
+    settings.validate().map_err(ServerError::from)?;
+
+    let remote_os = detect_remote_os(ssh_client).await?;
+    let settings = resolve_settings(ssh_client, settings).await?;
+    let installation = detect_installation(ssh_client, host, &settings).await?;
+    let wireguard_installed = wireguard_installed(ssh_client).await?;
+
+    build_plan(
+        remote_os,
+        &settings,
+        &installation,
+        existing,
+        wireguard_installed,
+    )
+}
+
+fn build_plan(
+    remote_os: RemoteOs,
+    settings: &ServerSettings,
+    installation: &Installation,
+    existing: ExistingInstall,
+    wireguard_installed: bool,
+) -> anyhow::Result<SetupPlan> {
+    let mut changes = Vec::new();
+
+    match (installation, existing) {
+        (Installation::None, _) => {
+            changes.extend(fresh_install_changes(
+                &remote_os,
+                settings,
+                wireguard_installed,
+            ));
+        }
+        (installation, ExistingInstall::Abort) => {
+            changes.push(format!(
+                "Nothing: WireGuard is already set up on {}. Join it as a new peer or reinstall it",
+                installation.interface().unwrap_or_default()
+            ));
+        }
+        (Installation::Managed(state), ExistingInstall::Join) => {
+            let ip = state.get_next_available_ip()?;
+            changes.push(format!(
+                "Add this device as a new peer with address {} on {}",
+                ip, state.settings.interface
+            ));
+            if state.settings.preshared_keys {
+                changes.push("Give the new peer a preshared key".to_string());
+            }
+            changes.push(format!(
+                "Keep the server keys, settings and all {} existing peers",
+                state.peers.len()
+            ));
+        }
+        (Installation::Unmanaged { interface }, ExistingInstall::Join) => {
+            return Err(ServerError::CannotJoin(interface.clone()).into());
+        }
+        (installation, ExistingInstall::Reinstall) => {
+            let revoked = match installation {
+                Installation::Managed(state) if !state.peers.is_empty() => {
+                    let names: Vec<&str> = state.peers.iter().map(|p| p.name.as_str()).collect();
+                    format!(
+                        "Revoke all {} existing peers: {}",
+                        names.len(),
+                        names.join(", ")
+                    )
+                }
+                _ => "Revoke every device configured on the existing interface".to_string(),
+            };
+            changes.push(format!(
+                "Replace the WireGuard setup on {} and its server keys",
+                installation.interface().unwrap_or_default()
+            ));
+            changes.push(revoked);
+            changes.extend(fresh_install_changes(
+                &remote_os,
+                settings,
+                wireguard_installed,
+            ));
+        }
+    }
+
+    Ok(SetupPlan {
+        os: remote_os.pretty_name,
+        existing: installation.summary(),
+        changes,
+    })
+}
+
+fn fresh_install_changes(
+    remote_os: &RemoteOs,
+    settings: &ServerSettings,
+    wireguard_installed: bool,
+) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    if !wireguard_installed {
+        changes.push(format!(
+            "Install WireGuard with {}",
+            tool_name(remote_os.package_manager)
+        ));
+    }
+
+    let mut addresses = format!(
+        "{} (server {})",
+        settings.subnet.trunc(),
+        settings.server_address()
+    );
+    if let (Some(subnet_v6), Some(address_v6)) = (settings.subnet_v6, settings.server_address_v6())
+    {
+        addresses.push_str(&format!(
+            " and {} (server {})",
+            subnet_v6.trunc(),
+            address_v6
+        ));
+    }
+
+    changes.push(format!(
+        "Write {} with new server keys, tunnel network {} and UDP port {}",
+        settings.config_path().display(),
+        addresses,
+        settings.listen_port
+    ));
+    changes.push(format!(
+        "Forward and NAT tunnel traffic out of {} using {}",
+        settings.egress_interface.as_deref().unwrap_or_default(),
+        tool_name(settings.firewall.unwrap_or_default())
+    ));
+    changes.push("Enable IP forwarding in /etc/sysctl.conf".to_string());
+    if let Some(host_firewall) = settings.host_firewall {
+        changes.push(format!(
+            "Allow UDP port {} and traffic from {} in {}",
+            settings.listen_port,
+            settings.interface,
+            tool_name(host_firewall)
+        ));
+    }
+    changes.push(format!(
+        "Start {} now and at boot with {}",
+        settings.interface,
+        tool_name(remote_os.service_manager)
+    ));
+
+    if let Some(resolver) = settings.resolver {
+        changes.push(format!(
+            "Install {} and answer DNS queries on the tunnel addresses",
+            tool_name(resolver)
+        ));
+    }
+
+    changes.push("Create the first peer for this device".to_string());
+
+    changes
+}
+
+fn tool_name(tool: impl std::fmt::Debug) -> String {
+    format!("{:?}", tool).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wireguard::{
+        firewall::{FirewallBackend, HostFirewall},
+        peer::Peer,
+        resolver::DnsResolver,
+        server::{PackageManager, ServiceManager},
+    };
+
+    fn debian() -> RemoteOs {
+        RemoteOs {
+            id: "debian".to_string(),
+            pretty_name: "Debian GNU/Linux 12 (bookworm)".to_string(),
+            package_manager: PackageManager::Apt,
+            service_manager: ServiceManager::Systemd,
+        }
+    }
+
+    // What `resolve_settings` hands over: egress and firewall are always set.
+    fn resolved() -> ServerSettings {
+        ServerSettings {
+            egress_interface: Some("eth0".to_string()),
+            firewall: Some(FirewallBackend::Iptables),
+            ..ServerSettings::default()
+        }
+    }
+
+    fn fresh_changes() -> Vec<&'static str> {
+        vec![
+            "Install WireGuard with apt",
+            "Write /etc/wireguard/wg0.conf with new server keys, tunnel network 10.0.0.0/24 (server 10.0.0.1) and UDP port 51820",
+            "Forward and NAT tunnel traffic out of eth0 using iptables",
+            "Enable IP forwarding in /etc/sysctl.conf",
+            "Start wg0 now and at boot with systemd",
+            "Create the first peer for this device",
+        ]
+    }
+
+    fn managed(preshared_keys: bool) -> Installation {
+        let settings = ServerSettings {
+            preshared_keys,
+            ..resolved()
+        };
+        let mut state = VpnState::new(
+            "server-key".to_string(),
+            "vpn.example.com".parse().unwrap(),
+            settings,
+        );
+        for (name, host) in [("laptop", 2), ("phone", 3)] {
+            let (peer, _) = Peer::new(name.to_string(), [10, 0, 0, host].into(), None, false);
+            state.peers.push(peer);
+        }
+
+        Installation::Managed(Box::new(state))
+    }
+
+    fn unmanaged() -> Installation {
+        Installation::Unmanaged {
+            interface: "wg0".to_string(),
+        }
+    }
+
+    fn plan(installation: &Installation, existing: ExistingInstall) -> SetupPlan {
+        build_plan(debian(), &resolved(), installation, existing, false).unwrap()
+    }
+
+    #[test]
+    fn fresh_server_lists_every_setup_step() {
+        let plan = plan(&Installation::None, ExistingInstall::Abort);
+
+        assert_eq!(plan.os, "Debian GNU/Linux 12 (bookworm)");
+        assert!(plan.existing.is_none());
+        assert_eq!(plan.changes, fresh_changes());
+    }
+
+    #[test]
+    fn fresh_server_lists_optional_steps() {
+        let settings = ServerSettings {
+            subnet_v6: Some("fd00:1234:5678::/64".parse().unwrap()),
+            firewall: Some(FirewallBackend::Nftables),
+            host_firewall: Some(HostFirewall::Ufw),
+            resolver: Some(DnsResolver::Unbound),
+            ..resolved()
+        };
+
+        let plan = build_plan(
+            debian(),
+            &settings,
+            &Installation::None,
+            ExistingInstall::Abort,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan.changes,
+            [
+                "Write /etc/wireguard/wg0.conf with new server keys, tunnel network 10.0.0.0/24 (server 10.0.0.1) and fd00:1234:5678::/64 (server fd00:1234:5678::1) and UDP port 51820",
+                "Forward and NAT tunnel traffic out of eth0 using nftables",
+                "Enable IP forwarding in /etc/sysctl.conf",
+                "Allow UDP port 51820 and traffic from wg0 in ufw",
+                "Start wg0 now and at boot with systemd",
+                "Install unbound and answer DNS queries on the tunnel addresses",
+                "Create the first peer for this device",
+            ]
+        );
+    }
+
+    #[test]
+    fn existing_setup_is_left_alone_by_default() {
+        for installation in [managed(false), unmanaged()] {
+            let plan = plan(&installation, ExistingInstall::Abort);
+
+            assert_eq!(
+                plan.changes,
+                [
+                    "Nothing: WireGuard is already set up on wg0. Join it as a new peer or reinstall it"
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn joining_a_managed_setup_adds_one_peer() {
+        let plan = plan(&managed(true), ExistingInstall::Join);
+
+        assert_eq!(
+            plan.changes,
+            [
+                "Add this device as a new peer with address 10.0.0.4 on wg0",
+                "Give the new peer a preshared key",
+                "Keep the server keys, settings and all 2 existing peers",
+            ]
+        );
+        let existing = plan.existing.unwrap();
+        assert!(existing.managed);
+        assert_eq!(existing.interface, "wg0");
+        assert_eq!(existing.peers, ["laptop", "phone"]);
+    }
+
+    #[test]
+    fn joining_an_unmanaged_setup_is_refused() {
+        let err = build_plan(
+            debian(),
+            &resolved(),
+            &unmanaged(),
+            ExistingInstall::Join,
+            false,
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<ServerError>(),
+            Some(ServerError::CannotJoin(interface)) if interface == "wg0"
+        ));
+    }
+
+    #[test]
+    fn reinstalling_a_managed_setup_names_the_revoked_peers() {
+        let plan = plan(&managed(false), ExistingInstall::Reinstall);
+
+        let mut expected = vec![
+            "Replace the WireGuard setup on wg0 and its server keys",
+            "Revoke all 2 existing peers: laptop, phone",
+        ];
+        expected.extend(fresh_changes());
+        assert_eq!(plan.changes, expected);
+    }
+
+    #[test]
+    fn replacing_an_unmanaged_setup_revokes_unknown_devices() {
+        let plan = plan(&unmanaged(), ExistingInstall::Reinstall);
+
+        let mut expected = vec![
+            "Replace the WireGuard setup on wg0 and its server keys",
+            "Revoke every device configured on the existing interface",
+        ];
+        expected.extend(fresh_changes());
+        assert_eq!(plan.changes, expected);
+        let existing = plan.existing.unwrap();
+        assert!(!existing.managed);
+        assert!(existing.peers.is_empty());
+    }
+}
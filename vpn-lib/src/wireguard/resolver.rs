@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ssh::{RemoteCommand, SshClient},
+    wireguard::{
+        server::{RemoteOs, remove_line_command},
+        settings::ServerSettings,
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsResolver {
+    Unbound,
+    Dnsmasq,
+}
+
+impl DnsResolver {
+    fn package(&self) -> &'static str {
+        match self {
+            DnsResolver::Unbound => "unbound",
+            DnsResolver::Dnsmasq => "dnsmasq",
+        }
+    }
+
+    fn config_path(&self) -> &'static str {
+        match self {
+            DnsResolver::Unbound => "/etc/unbound/wireguard.conf",
+            DnsResolver::Dnsmasq => "/etc/dnsmasq-wireguard.conf",
+        }
+    }
+
+    fn include(&self) -> (&'static str, String) {
+        match self {
+            DnsResolver::Unbound => (
+                "/etc/unbound/unbound.conf",
+                format!("include: \"{}\"", self.config_path()),
+            ),
+            DnsResolver::Dnsmasq => (
+                "/etc/dnsmasq.conf",
+                format!("conf-file={}", self.config_path()),
+            ),
+        }
+    }
+
+    /// Both resolvers bind addresses that may not exist yet, since the
+    /// WireGuard interface can come up after them.
+    pub fn config(&self, settings: &ServerSettings) -> String {
+        let addresses: Vec<String> = std::iter::once(settings.server_address().to_string())
+            .chain(settings.server_address_v6().map(|ip| ip.to_string()))
+            .collect();
+        let subnets: Vec<String> = std::iter::once(settings.subnet.trunc().to_string())
+            .chain(settings.subnet_v6.map(|net| net.trunc().to_string()))
+            .collect();
+
+        match self {
+            DnsResolver::Unbound => {
+                let mut config = String::from("server:\n");
+                for address in &addresses {
+                    config.push_str(&format!("    interface: {}\n", address));
+                }
+                for subnet in &subnets {
+                    config.push_str(&format!("    access-control: {} allow\n", subnet));
+                }
+                config.push_str("    ip-freebind: yes\n    hide-identity: yes\n    hide-version: yes\n");
+                config
+            }
+            DnsResolver::Dnsmasq => {
+                let mut config = String::new();
+                for address in &addresses {
+                    config.push_str(&format!("listen-address={}\n", address));
+                }
+                config.push_str("bind-dynamic\ndomain-needed\nbogus-priv\ncache-size=1000\n");
+                config
+            }
+        }
+    }
+}
+
+pub async fn install_resolver(
+    ssh_client: &SshClient,
+    remote_os: &RemoteOs,
+    resolver: DnsResolver,
+    settings: &ServerSettings,
+) -> anyhow::Result<()> {
+    let (output, status) = ssh_client
+        .run(&remote_os.package_manager.install_package_command(resolver.package()))
+        .await?;
+    if status != 0 {
+        anyhow::bail!("Failed to install {}: {}", resolver.package(), output.trim());
+    }
+
+    ssh_client
+        .upload(
+            Path::new(resolver.config_path()),
+            resolver.config(settings).as_bytes(),
+            0o644,
+            None,
+        )
+        .await?;
+
+    let (main_config, include_line) = resolver.include();
+    let add_include = RemoteCommand::shell("grep -qxF \"$1\" \"$0\" || echo \"$1\" >> \"$0\"")
+        .args([main_config.to_string(), include_line]);
+    ssh_client.run(&add_include).await?;
+
+    let (output, status) = ssh_client
+        .run(&remote_os.service_manager.restart_service_command(resolver.package()))
+        .await?;
+    if status != 0 {
+        anyhow::bail!("Failed to start {}: {}", resolver.package(), output.trim());
+    }
+
+    Ok(())
+}
+
+pub async fn uninstall_resolver(
+    ssh_client: &SshClient,
+    remote_os: &RemoteOs,
+    resolver: DnsResolver,
+) -> anyhow::Result<()> {
+    ssh_client
+        .run(&remote_os.service_manager.stop_service_command(resolver.package()))
+        .await?;
+
+    let (main_config, include_line) = resolver.include();
+    ssh_client
+        .run(&remove_line_command(main_config, &include_line))
+        .await?;
+    ssh_client
+        .run(&RemoteCommand::new("rm").args(["-f", resolver.config_path()]))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use std::net::IpAddr;
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use serde::{Deserialize, Serialize};
+
+use crate::wireguard::{server::TunnelMode, settings::ServerSettings};
+
+/// An empty `include` list means everything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouteProfile {
+    pub include: Vec<IpNet>,
+    pub exclude: Vec<IpNet>,
+}
+
+/// The server's own endpoint is kept out of split routes, or wg-quick would
+/// send the encrypted packets into the tunnel itself.
+pub fn allowed_ips(
+    tunnel_mode: TunnelMode,
+    routes: &RouteProfile,
+    settings: &ServerSettings,
+    endpoint: Option<IpAddr>,
+) -> Vec<IpNet> {
+    let everything = || vec![IpNet::V4(Ipv4Net::default()), IpNet::V6(Ipv6Net::default())];
+
+    if tunnel_mode == TunnelMode::Full {
+        return everything();
+    }
+
+    let included = if routes.include.is_empty() {
+        everything()
+    } else {
+        routes.include.clone()
+    };
+
+    let mut excluded = routes.exclude.clone();
+    excluded.extend(endpoint.map(IpNet::from));
+
+    let mut nets = subtract_all(&included, &excluded);
+    nets.push(IpNet::V4(settings.subnet.trunc()));
+    if let Some(subnet_v6) = settings.subnet_v6 {
+        nets.push(IpNet::V6(subnet_v6.trunc()));
+    }
+
+    IpNet::aggregate(&nets)
+}
+
+pub fn subtract_all(include: &[IpNet], exclude: &[IpNet]) -> Vec<IpNet> {
+    let mut remaining: Vec<IpNet> = include.iter().map(IpNet::trunc).collect();
+
+    for excluded in exclude {
+        remaining = remaining
+            .iter()
+            .flat_map(|net| subtract(*net, excluded.trunc()))
+            .collect();
+    }
+
+    IpNet::aggregate(&remaining)
+}
+
+pub fn subtract(net: IpNet, excluded: IpNet) -> Vec<IpNet> {
+    if excluded.contains(&net) {
+        return Vec::new();
+    }
+
+    if !net.contains(&excluded) {
+        // Different families or disjoint ranges: CIDR blocks either nest
+        // or do not overlap at all.
+        return vec![net];
+    }
+
+    match net.subnets(net.prefix_len() + 1) {
+        Ok(halves) => halves.flat_map(|half| subtract(half, excluded)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nets(values: &[&str]) -> Vec<IpNet> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    fn covers(nets: &[IpNet], ip: &str) -> bool {
+        let ip: IpAddr = ip.parse().unwrap();
+        nets.iter().any(|net| net.contains(&ip))
+    }
+
+    #[test]
+    fn subtract_splits_around_an_inner_exclude() {
+        let mut remaining = subtract(
+            "10.0.0.0/8".parse().unwrap(),
+            "10.1.0.0/16".parse().unwrap(),
+        );
+        remaining.sort();
+
+        assert_eq!(
+            remaining,
+            nets(&[
+                "10.0.0.0/16",
+                "10.2.0.0/15",
+                "10.4.0.0/14",
+                "10.8.0.0/13",
+                "10.16.0.0/12",
+                "10.32.0.0/11",
+                "10.64.0.0/10",
+                "10.128.0.0/9",
+            ])
+        );
+    }
+
+    #[test]
+    fn subtract_removes_a_fully_excluded_net() {
+        let net = "10.1.0.0/16".parse().unwrap();
+
+        assert!(subtract(net, "10.0.0.0/8".parse().unwrap()).is_empty());
+        assert!(subtract(net, net).is_empty());
+        assert_eq!(
+            subtract_all(&[net], &nets(&["10.1.0.0/17", "10.1.128.0/17"])),
+            []
+        );
+    }
+
+    #[test]
+    fn subtract_keeps_disjoint_and_other_family_nets() {
+        let net: IpNet = "10.0.0.0/8".parse().unwrap();
+
+        assert_eq!(subtract(net, "192.168.0.0/16".parse().unwrap()), [net]);
+        assert_eq!(subtract(net, "::/0".parse().unwrap()), [net]);
+    }
+
+    #[test]
+    fn subtract_all_handles_both_families() {
+        let remaining = subtract_all(
+            &nets(&["0.0.0.0/0", "::/0"]),
+            &nets(&["192.168.0.0/16", "fd00::/8"]),
+        );
+
+        assert!(!covers(&remaining, "192.168.1.1"));
+        assert!(!covers(&remaining, "fd00::1"));
+        assert!(covers(&remaining, "8.8.8.8"));
+        assert!(covers(&remaining, "2606:4700:4700::1111"));
+        assert_eq!(
+            remaining.iter().filter(|net| net.addr().is_ipv4()).count(),
+            16
+        );
+        assert_eq!(
+            remaining.iter().filter(|net| net.addr().is_ipv6()).count(),
+            8
+        );
+    }
+
+    #[test]
+    fn subtract_all_with_no_includes_is_empty() {
+        assert_eq!(subtract_all(&[], &nets(&["10.0.0.0/8"])), []);
+    }
+
+    #[test]
+    fn full_tunnel_claims_everything() {
+        let routes = RouteProfile {
+            include: nets(&["10.0.0.0/8"]),
+            exclude: nets(&["192.168.0.0/16"]),
+        };
+
+        assert_eq!(
+            allowed_ips(
+                TunnelMode::Full,
+                &routes,
+                &ServerSettings::default(),
+                Some("203.0.113.5".parse().unwrap()),
+            ),
+            nets(&["0.0.0.0/0", "::/0"])
+        );
+    }
+
+    #[test]
+    fn split_tunnel_without_includes_routes_everything_but_the_excludes() {
+        let routes = RouteProfile {
+            include: Vec::new(),
+            exclude: nets(&["192.168.0.0/16"]),
+        };
+
+        let allowed = allowed_ips(TunnelMode::Split, &routes, &ServerSettings::default(), None);
+
+        assert!(!covers(&allowed, "192.168.1.1"));
+        assert!(covers(&allowed, "8.8.8.8"));
+        assert!(covers(&allowed, "2606:4700:4700::1111"));
+    }
+
+    #[test]
+    fn split_tunnel_keeps_the_subnet_and_drops_the_endpoint() {
+        let settings = ServerSettings {
+            subnet_v6: Some("fd12:3456:789a::/64".parse().unwrap()),
+            ..ServerSettings::default()
+        };
+        let routes = RouteProfile {
+            include: nets(&["198.51.100.0/24", "203.0.113.0/24"]),
+            exclude: nets(&["10.0.0.0/8"]),
+        };
+
+        let allowed = allowed_ips(
+            TunnelMode::Split,
+            &routes,
+            &settings,
+            Some("203.0.113.5".parse().unwrap()),
+        );
+
+        assert!(covers(&allowed, "198.51.100.1"));
+        assert!(covers(&allowed, "203.0.113.4"));
+        assert!(!covers(&allowed, "203.0.113.5"));
+        assert!(covers(&allowed, "10.0.0.1"));
+        assert!(covers(&allowed, "fd12:3456:789a::1"));
+        assert!(!covers(&allowed, "8.8.8.8"));
+    }
+}
@@ -1,24 +1,41 @@
-use crate::ssh::{SshClient, SshSession};
+use crate::ssh::{OutputChunk, RemoteCommand, SshClient, SshSession};
 use crate::{
+    host::Host,
     ssh::run_remote_cmd,
     wireguard::{
-        peer::Peer,
-        state::{VpnState, save_state},
+        config::{InterfaceSection, PeerSection, WgConfig},
+        firewall::{
+            FirewallBackend, close_host_firewall, detect_firewall_backend, detect_host_firewall,
+            open_host_firewall,
+        },
+        peer::{Peer, add_peer},
+        provision::{ExistingInstall, Installation, detect_installation},
+        resolver::{install_resolver, uninstall_resolver},
+        settings::{ClientSettings, ServerSettings, SettingsError, is_valid_interface_name},
+        state::{STATE_PATH, VpnState, load_state, save_state},
     },
 };
 use base64::{Engine, engine::general_purpose};
-use rand_core::OsRng;
+use ipnet::IpNet;
+use rand_core::{OsRng, RngCore};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
-use std::{net::Ipv4Addr, path::Path};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    path::Path,
+    time::Duration,
+};
 use x25519_dalek::{PublicKey, StaticSecret};
 
 #[derive(Debug, Clone)]
 pub struct SetupResult {
     pub client_private_key: SecretString,
+    pub client_preshared_key: Option<SecretString>,
     pub server_public_key: String,
     pub client_ip: Ipv4Addr,
-    pub public_ip: Ipv4Addr,
+    pub client_ipv6: Option<Ipv6Addr>,
+    pub host: Host,
+    pub settings: ServerSettings,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -29,8 +46,307 @@ pub enum ServerError {
     KeyNotFound,
     #[error("Internal state error: {0}")]
     State(#[from] crate::wireguard::state::StateError),
+    #[error("Unsupported server OS: {0}")]
+    UnsupportedOs(String),
+    #[error("Invalid server settings: {0}")]
+    Settings(#[from] SettingsError),
+    #[error("Could not find the server's default route interface: {0}")]
+    EgressNotFound(String),
+    #[error("Network interface '{0}' does not exist on the server")]
+    UnknownInterface(String),
+    #[error("WireGuard is already set up on {0}: join it as a new peer or reinstall it")]
+    AlreadyInstalled(String),
+    #[error("The WireGuard setup on {0} was not created by this app and can only be reinstalled")]
+    CannotJoin(String),
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageManager {
+    Apt,
+    Dnf,
+    Yum,
+    Apk,
+    Pacman,
+}
+
+impl PackageManager {
+    pub fn install_command(&self) -> RemoteCommand {
+        let cmd = match self {
+            PackageManager::Apt => RemoteCommand::shell(
+                "export DEBIAN_FRONTEND=noninteractive && apt-get update -y && \
+                 apt-get install -y -q wireguard iptables",
+            ),
+            PackageManager::Dnf => {
+                RemoteCommand::new("dnf").args(["install", "-y", "wireguard-tools", "iptables"])
+            }
+            PackageManager::Yum => RemoteCommand::shell(
+                "yum install -y epel-release && yum install -y wireguard-tools iptables",
+            ),
+            PackageManager::Apk => RemoteCommand::new("apk").args([
+                "add",
+                "--no-cache",
+                "wireguard-tools",
+                "iptables",
+                "ip6tables",
+            ]),
+            PackageManager::Pacman => RemoteCommand::new("pacman").args([
+                "-Sy",
+                "--noconfirm",
+                "--needed",
+                "wireguard-tools",
+                "iptables",
+            ]),
+        };
+
+        cmd.timeout(INSTALL_TIMEOUT)
+    }
+
+    pub fn install_package_command(&self, package: &str) -> RemoteCommand {
+        let cmd = match self {
+            PackageManager::Apt => RemoteCommand::shell(
+                "export DEBIAN_FRONTEND=noninteractive && apt-get update -y && apt-get install -y -q \"$0\"",
+            )
+            .arg(package),
+            PackageManager::Dnf => RemoteCommand::new("dnf").args(["install", "-y", package]),
+            PackageManager::Yum => RemoteCommand::new("yum").args(["install", "-y", package]),
+            PackageManager::Apk => RemoteCommand::new("apk").args(["add", "--no-cache", package]),
+            PackageManager::Pacman => {
+                RemoteCommand::new("pacman").args(["-Sy", "--noconfirm", "--needed", package])
+            }
+        };
+
+        cmd.timeout(INSTALL_TIMEOUT)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceManager {
+    Systemd,
+    OpenRc,
+}
+
+impl ServiceManager {
+    pub fn enable_command(&self, interface: &str) -> RemoteCommand {
+        match self {
+            ServiceManager::Systemd => RemoteCommand::new("systemctl")
+                .args(["enable".to_string(), format!("wg-quick@{}", interface)]),
+            ServiceManager::OpenRc => RemoteCommand::shell(
+                "ln -sf /etc/init.d/wg-quick \"/etc/init.d/wg-quick.$0\" && rc-update add \"wg-quick.$0\" default",
+            )
+            .arg(interface),
+        }
+    }
+
+    pub fn disable_command(&self, interface: &str) -> RemoteCommand {
+        match self {
+            ServiceManager::Systemd => RemoteCommand::new("systemctl")
+                .args(["disable".to_string(), format!("wg-quick@{}", interface)]),
+            ServiceManager::OpenRc => RemoteCommand::shell(
+                "rc-update del \"wg-quick.$0\" default; rm -f \"/etc/init.d/wg-quick.$0\"",
+            )
+            .arg(interface),
+        }
+    }
+
+    pub fn stop_service_command(&self, service: &str) -> RemoteCommand {
+        match self {
+            ServiceManager::Systemd => {
+                RemoteCommand::new("systemctl").args(["disable", "--now", service])
+            }
+            ServiceManager::OpenRc => {
+                RemoteCommand::shell("rc-service \"$0\" stop; rc-update del \"$0\" default")
+                    .arg(service)
+            }
+        }
+    }
+
+    pub fn restart_service_command(&self, service: &str) -> RemoteCommand {
+        match self {
+            ServiceManager::Systemd => {
+                RemoteCommand::shell("systemctl enable \"$0\" && systemctl restart \"$0\"")
+                    .arg(service)
+            }
+            ServiceManager::OpenRc => {
+                RemoteCommand::shell("rc-update add \"$0\" default && rc-service \"$0\" restart")
+                    .arg(service)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RemoteOs {
+    pub id: String,
+    pub pretty_name: String,
+    pub package_manager: PackageManager,
+    pub service_manager: ServiceManager,
+}
+
+fn parse_os_release(content: &str) -> (String, Vec<String>, String) {
+    let mut id = String::new();
+    let mut id_like = Vec::new();
+    let mut pretty_name = String::new();
+
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').trim_matches('\'');
+
+        match key.trim() {
+            "ID" => id = value.to_lowercase(),
+            "ID_LIKE" => id_like = value.split_whitespace().map(|s| s.to_lowercase()).collect(),
+            "PRETTY_NAME" => pretty_name = value.to_string(),
+            _ => {}
+        }
+    }
+
+    (id, id_like, pretty_name)
+}
+
+pub async fn detect_remote_os(ssh_client: &SshClient) -> anyhow::Result<RemoteOs> {
+    let (os_release, status) = ssh_client
+        .run_raw(&RemoteCommand::new("cat").arg("/etc/os-release"))
+        .await?;
+
+    if status != 0 {
+        return Err(ServerError::UnsupportedOs("/etc/os-release is missing".into()).into());
+    }
+
+    let (id, id_like, pretty_name) = parse_os_release(&os_release);
+    let family: Vec<&str> = std::iter::once(id.as_str())
+        .chain(id_like.iter().map(String::as_str))
+        .collect();
+
+    let package_manager = if family.iter().any(|f| matches!(*f, "debian" | "ubuntu")) {
+        PackageManager::Apt
+    } else if family.iter().any(|f| matches!(*f, "fedora" | "rhel" | "centos")) {
+        let (_, has_dnf) = ssh_client
+            .run_raw(&RemoteCommand::shell("command -v dnf"))
+            .await?;
+        if has_dnf == 0 {
+            PackageManager::Dnf
+        } else {
+            PackageManager::Yum
+        }
+    } else if family.contains(&"alpine") {
+        PackageManager::Apk
+    } else if family.contains(&"arch") {
+        PackageManager::Pacman
+    } else {
+        return Err(ServerError::UnsupportedOs(pretty_name).into());
+    };
+
+    let (init, _) = ssh_client
+        .run_raw(&RemoteCommand::shell(
+            "if [ -d /run/systemd/system ]; then echo systemd; \
+             elif command -v rc-update > /dev/null; then echo openrc; fi",
+        ))
+        .await?;
+
+    let service_manager = match init.trim() {
+        "systemd" => ServiceManager::Systemd,
+        "openrc" => ServiceManager::OpenRc,
+        _ => {
+            return Err(ServerError::UnsupportedOs(format!(
+                "{} (no systemd or OpenRC found)",
+                pretty_name
+            ))
+            .into());
+        }
+    };
+
+    Ok(RemoteOs {
+        id,
+        pretty_name,
+        package_manager,
+        service_manager,
+    })
+}
+
+#[derive(Deserialize)]
+struct IpRoute {
+    dev: Option<String>,
+}
+
+pub async fn detect_egress_interface(ssh_client: &SshClient) -> anyhow::Result<String> {
+    let (output, status) = ssh_client
+        .run_raw(&RemoteCommand::new("ip").args(["-j", "route", "show", "default"]))
+        .await?;
+
+    let from_json = (status == 0)
+        .then(|| serde_json::from_str::<Vec<IpRoute>>(&output).ok())
+        .flatten()
+        .and_then(|routes| routes.into_iter().find_map(|r| r.dev));
+
+    let interface = match from_json {
+        Some(interface) => interface,
+        None => {
+            let (output, _) = ssh_client
+                .run_raw(&RemoteCommand::new("ip").args(["route", "show", "default"]))
+                .await?;
+
+            output
+                .split_whitespace()
+                .skip_while(|word| *word != "dev")
+                .nth(1)
+                .map(str::to_string)
+                .ok_or_else(|| ServerError::EgressNotFound(output.trim().to_string()))?
+        }
+    };
+
+    validate_egress_interface(ssh_client, &interface).await?;
+
+    Ok(interface)
+}
+
+pub async fn validate_egress_interface(
+    ssh_client: &SshClient,
+    interface: &str,
+) -> anyhow::Result<()> {
+    if !is_valid_interface_name(interface) {
+        let error = SettingsError::InvalidInterface(interface.to_string());
+        return Err(ServerError::Settings(error).into());
+    }
+
+    let (_, status) = ssh_client
+        .run_raw(&RemoteCommand::new("ip").args(["link", "show", "dev", interface]))
+        .await?;
+
+    if status != 0 {
+        return Err(ServerError::UnknownInterface(interface.to_string()).into());
+    }
+
+    Ok(())
 }
 
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SetupStep {
+    Install,
+    Keygen,
+    ConfigUpload,
+    Firewall,
+    InterfaceUp,
+    EnableService,
+    Resolver,
+    Harden,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SetupProgress {
+    StepStarted { step: SetupStep },
+    Output { step: SetupStep, chunk: String },
+    StepFinished { step: SetupStep },
+}
+
+const INSTALL_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+pub type ProgressReporter<'a> = &'a (dyn Fn(SetupProgress) + Send + Sync);
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum TunnelMode {
@@ -48,137 +364,455 @@ pub fn generate_keys() -> (SecretString, String) {
     (SecretString::new(priv_b64.into()), pub_b64)
 }
 
+pub fn generate_preshared_key() -> SecretString {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+
+    SecretString::new(general_purpose::STANDARD.encode(key).into())
+}
+
+pub fn public_key_for(private_key: &str) -> anyhow::Result<String> {
+    let bytes: [u8; 32] = general_purpose::STANDARD
+        .decode(private_key.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("A WireGuard key must be 32 bytes"))?;
+    let public = PublicKey::from(&StaticSecret::from(bytes));
+
+    Ok(general_purpose::STANDARD.encode(public.to_bytes()))
+}
+
+pub async fn set_preshared_key(
+    ssh_client: &SshClient,
+    interface: &str,
+    public_key: &str,
+    preshared_key: &SecretString,
+) -> anyhow::Result<()> {
+    let cmd = RemoteCommand::new("wg").args([
+        "set",
+        interface,
+        "peer",
+        public_key,
+        "preshared-key",
+        "/dev/stdin",
+    ]);
+
+    let (output, status) = ssh_client
+        .run_with_input(&cmd, preshared_key.expose_secret().as_bytes())
+        .await?;
+    if status != 0 {
+        anyhow::bail!("Failed to set the preshared key: {}", output.trim());
+    }
+
+    Ok(())
+}
+
 fn build_server_config(
     server_private_key: &str,
-    client_public_key: &str,
-    interface: &str,
-    client_ip: Ipv4Addr,
+    peer: &Peer,
+    preshared_key: Option<&str>,
+    egress_interface: &str,
+    firewall: FirewallBackend,
+    settings: &ServerSettings,
 ) -> String {
-    format!(
-        r#"[Interface]
-Address = 10.0.0.1/24
-ListenPort = 51820
-PrivateKey = {server_private_key}
-
-PostUp = sysctl -w net.ipv4.ip_forward=1; iptables -A FORWARD -i %i -j ACCEPT; iptables -t nat -A POSTROUTING -o {interface} -j MASQUERADE
-PostDown = iptables -D FORWARD -i %i -j ACCEPT; iptables -t nat -D POSTROUTING -o {interface} -j MASQUERADE
-
-PostUp = ip6tables -A FORWARD -i %i -j REJECT
-PostDown = ip6tables -D FORWARD -i %i -j REJECT
-
-[Peer]
-PublicKey = {client_public_key}
-AllowedIPs = {client_ip}/32
-        "#
-    )
+    let mut interface = InterfaceSection {
+        private_key: Some(server_private_key.to_string()),
+        address: settings
+            .interface_addresses(settings.server_address(), settings.server_address_v6()),
+        listen_port: Some(settings.listen_port),
+        mtu: settings.mtu,
+        ..InterfaceSection::default()
+    };
+    firewall.add_hooks(&mut interface, settings, egress_interface);
+
+    WgConfig {
+        interface,
+        peers: vec![peer.section(preshared_key)],
+    }
+    .to_string()
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn build_client_config(
     client_priv: &str,
+    preshared_key: Option<&str>,
     server_pub: &str,
-    public_ip: Ipv4Addr,
+    server_host: &Host,
     peer_ip: Ipv4Addr,
-    tunnel_mode: &TunnelMode,
+    peer_ipv6: Option<Ipv6Addr>,
+    settings: &ServerSettings,
+    allowed_ips: &[IpNet],
+    client: &ClientSettings,
 ) -> String {
-    let allowed_ips = match tunnel_mode {
-        TunnelMode::Full => "0.0.0.0/0",
-        TunnelMode::Split => "0.0.0.0/24",
+    let interface = InterfaceSection {
+        private_key: Some(client_priv.to_string()),
+        address: settings.interface_addresses(peer_ip, peer_ipv6),
+        mtu: client.mtu.or(settings.mtu),
+        dns: client.dns.clone(),
+        dns_search: client.dns_search.clone(),
+        ..InterfaceSection::default()
     };
 
-    format!(
-        r#"[Interface]
-PrivateKey = {client_priv}
-Address = {peer_ip}/24
-DNS = 1.1.1.1
-
-[Peer]
-PublicKey = {server_pub}
-Endpoint = {public_ip}:51820
-AllowedIPs = {allowed_ips}
-"#
-    )
+    let server = PeerSection {
+        public_key: server_pub.to_string(),
+        preshared_key: preshared_key.map(str::to_string),
+        endpoint: Some(server_host.with_port(settings.listen_port)),
+        allowed_ips: allowed_ips.to_vec(),
+        persistent_keepalive: client.persistent_keepalive,
+        ..PeerSection::default()
+    };
+
+    WgConfig {
+        interface,
+        peers: vec![server],
+    }
+    .to_string()
 }
 
 pub async fn upload_file(ssh_client: &SshClient, path: &Path, content: &str) -> anyhow::Result<()> {
-    let b64_content = general_purpose::STANDARD.encode(content);
-    let cmd = format!(
-        "echo '{}' | base64 -d | {} tee {} > /dev/null",
-        b64_content,
-        ssh_client.sudo_prefix,
-        path.display()
-    );
+    ssh_client.upload(path, content.as_bytes(), 0o600, None).await
+}
 
-    let (output, status) = ssh_client.exec_raw(&cmd).await?;
+pub(crate) async fn resolve_settings(
+    ssh_client: &SshClient,
+    settings: &ServerSettings,
+) -> anyhow::Result<ServerSettings> {
+    let egress_interface = match &settings.egress_interface {
+        Some(interface) => {
+            validate_egress_interface(ssh_client, interface).await?;
+            interface.clone()
+        }
+        None => detect_egress_interface(ssh_client).await?,
+    };
 
-    if status != 0 {
-        anyhow::bail!("Failed to upload file to {}: {}", path.display(), output);
-    }
+    let firewall = match settings.firewall {
+        Some(firewall) => firewall,
+        None => detect_firewall_backend(ssh_client).await?,
+    };
 
-    ssh_client
-        .exec(&format!("chmod 600 {}", path.display()))
+    Ok(ServerSettings {
+        egress_interface: Some(egress_interface),
+        firewall: Some(firewall),
+        host_firewall: detect_host_firewall(ssh_client).await?,
+        ..settings.clone()
+    })
+}
+
+pub(crate) fn remove_line_command(path: &str, line: &str) -> RemoteCommand {
+    RemoteCommand::shell(
+        "[ -f \"$0\" ] || exit 0; tmp=$(mktemp) && grep -vxF \"$1\" \"$0\" > \"$tmp\"; cat \"$tmp\" > \"$0\"; rm -f \"$tmp\"",
+    )
+    .args([path, line])
+}
+
+pub(crate) async fn wireguard_installed(ssh_client: &SshClient) -> anyhow::Result<bool> {
+    let (_, status) = ssh_client
+        .run_raw(&RemoteCommand::shell("command -v wg"))
         .await?;
 
-    Ok(())
+    Ok(status == 0)
 }
 
 pub async fn setup_wireguard(
     ssh_client: &SshClient,
-    public_ip: Ipv4Addr,
-    interface: &str,
+    host: &Host,
+    settings: &ServerSettings,
+    existing: ExistingInstall,
+    on_progress: ProgressReporter<'_>,
 ) -> anyhow::Result<SetupResult> {
-    let (_, status) = ssh_client.exec_raw("which wg").await?;
-
-    if status != 0 {
-        let apt_cmd = "DEBIAN_FRONTEND=noninteractive apt-get update -y && \
-                   DEBIAN_FRONTEND=noninteractive apt-get install -y -q wireguard iptables";
+    settings.validate().map_err(ServerError::from)?;
+
+    ssh_client.ensure_not_cancelled()?;
+    on_progress(SetupProgress::StepStarted {
+        step: SetupStep::Install,
+    });
+
+    let remote_os = detect_remote_os(ssh_client).await?;
+    let settings = &resolve_settings(ssh_client, settings).await?;
+    let egress_interface = settings.egress_interface.clone().unwrap_or_default();
+    let firewall = settings.firewall.unwrap_or_default();
+
+    match (detect_installation(ssh_client, host, settings).await?, existing) {
+        (Installation::None, _) => {}
+        (installation, ExistingInstall::Abort) => {
+            let interface = installation.interface().unwrap_or_default().to_string();
+            return Err(ServerError::AlreadyInstalled(interface).into());
+        }
+        (Installation::Managed(state), ExistingInstall::Join) => {
+            return join_installation(ssh_client, host, *state, on_progress).await;
+        }
+        (Installation::Unmanaged { interface }, ExistingInstall::Join) => {
+            return Err(ServerError::CannotJoin(interface).into());
+        }
+        (Installation::Managed(_), ExistingInstall::Reinstall) => {
+            teardown_wireguard(ssh_client).await?;
+        }
+        // Not ours to tear down, but the new config replaces it under the same
+        // name. Its PostDown hooks only run from the old config, so the
+        // interface goes down before that is overwritten.
+        (Installation::Unmanaged { interface }, ExistingInstall::Reinstall) => {
+            ssh_client
+                .run(&RemoteCommand::new("wg-quick").args(["down", interface.as_str()]))
+                .await?;
+        }
+    }
 
-        let (output, install_status) = ssh_client.exec(apt_cmd).await?;
+    if !wireguard_installed(ssh_client).await? {
+        let install_cmd = remote_os.package_manager.install_command();
+
+        let (output, install_status) = ssh_client
+            .run_streaming(&install_cmd, &mut |chunk| {
+                let (OutputChunk::Stdout(chunk) | OutputChunk::Stderr(chunk)) = chunk;
+                on_progress(SetupProgress::Output {
+                    step: SetupStep::Install,
+                    chunk,
+                });
+            })
+            .await?;
 
         if install_status != 0 {
-            println!("Wireguard installation failed: {}", output);
-            anyhow::bail!("Wireguard installation failed");
+            anyhow::bail!(
+                "Wireguard installation failed on {}: {}",
+                remote_os.pretty_name,
+                output.trim()
+            );
         }
     }
-    let (server_priv, server_pub) = generate_keys();
-    let (new_peer, peer_priv_key) = Peer::new("initial-client".into(), Ipv4Addr::new(10, 0, 0, 2));
 
-    let mut state = VpnState::new(server_pub.clone(), public_ip);
+    on_progress(SetupProgress::StepFinished {
+        step: SetupStep::Install,
+    });
+    ssh_client.ensure_not_cancelled()?;
+    on_progress(SetupProgress::StepStarted {
+        step: SetupStep::Keygen,
+    });
+
+    let (server_priv, server_pub) = generate_keys();
+    let mut state = VpnState::new(server_pub.clone(), host.clone(), settings.clone());
+
+    let client_ip = state.get_next_available_ip()?;
+    let (new_peer, peer_keys) = Peer::new(
+        "initial-client".into(),
+        client_ip,
+        settings.ipv6_for(client_ip),
+        settings.preshared_keys,
+    );
     state.peers.push(new_peer.clone());
 
     let server_config = build_server_config(
         &server_priv.expose_secret(),
-        &new_peer.public_key,
-        interface,
-        new_peer.ip,
+        &new_peer,
+        peer_keys.preshared_key.as_ref().map(|key| key.expose_secret()),
+        &egress_interface,
+        firewall,
+        settings,
     );
 
-    ssh_client.exec("mkdir -p /etc/wireguard").await?;
-    ssh_client.exec("chmod 700 /etc/wireguard").await?;
+    on_progress(SetupProgress::StepFinished {
+        step: SetupStep::Keygen,
+    });
+    ssh_client.ensure_not_cancelled()?;
+    on_progress(SetupProgress::StepStarted {
+        step: SetupStep::ConfigUpload,
+    });
+
+    ssh_client
+        .run(&RemoteCommand::new("mkdir").args(["-p", "/etc/wireguard"]))
+        .await?;
+    ssh_client
+        .run(&RemoteCommand::new("chmod").args(["700", "/etc/wireguard"]))
+        .await?;
+
+    upload_file(ssh_client, &settings.config_path(), &server_config).await?;
+
+    let mut forwarding_keys = vec!["net.ipv4.ip_forward"];
+    if settings.subnet_v6.is_some() {
+        forwarding_keys.push("net.ipv6.conf.all.forwarding");
+    }
+
+    for key in forwarding_keys {
+        let (value, _) = ssh_client
+            .run(&RemoteCommand::new("sysctl").args(["-n", key]))
+            .await?;
+        if value.trim() != "1" {
+            ssh_client
+                .run(&RemoteCommand::new("sysctl").args(["-w".to_string(), format!("{}=1", key)]))
+                .await?;
+            state.forwarding.enabled.push(key.to_string());
+        }
+
+        let line = format!("{}=1", key);
+        let (_, status) = ssh_client
+            .run(&RemoteCommand::new("grep").args(["-qxF", line.as_str(), "/etc/sysctl.conf"]))
+            .await?;
+        if status != 0 {
+            ssh_client
+                .run(&RemoteCommand::shell("echo \"$0\" >> /etc/sysctl.conf").arg(line))
+                .await?;
+            state.forwarding.persisted.push(key.to_string());
+        }
+    }
+
+    on_progress(SetupProgress::StepFinished {
+        step: SetupStep::ConfigUpload,
+    });
+
+    if let Some(host_firewall) = settings.host_firewall {
+        ssh_client.ensure_not_cancelled()?;
+        on_progress(SetupProgress::StepStarted {
+            step: SetupStep::Firewall,
+        });
+
+        open_host_firewall(ssh_client, host_firewall, settings).await?;
+
+        on_progress(SetupProgress::StepFinished {
+            step: SetupStep::Firewall,
+        });
+    }
 
-    let config_path = Path::new("/etc/wireguard/wg0.conf");
-    upload_file(ssh_client, config_path, &server_config).await?;
+    ssh_client.ensure_not_cancelled()?;
+    on_progress(SetupProgress::StepStarted {
+        step: SetupStep::InterfaceUp,
+    });
 
-    ssh_client.exec("sysctl -w net.ipv4.ip_forward=1").await?;
     ssh_client
-        .exec("echo 'net.ipv4.ip_forward=1' >> /etc/sysctl.conf || true")
+        .run(&RemoteCommand::new("wg-quick").args(["down", settings.interface.as_str()]))
         .await?;
 
-    ssh_client.exec("wg-quick down wg0 || true").await?;
-   
-    let (output, status) =  ssh_client.exec("wg-quick up wg0").await?;
+    let (output, status) = ssh_client
+        .run(&RemoteCommand::new("wg-quick").args(["up", settings.interface.as_str()]))
+        .await?;
     if status != 0 {
         anyhow::bail!("Failed to start Wireguard: {}", output);
     }
 
-    ssh_client.exec("systemctl enable wg-quick@wg0").await?;
+    on_progress(SetupProgress::StepFinished {
+        step: SetupStep::InterfaceUp,
+    });
+    ssh_client.ensure_not_cancelled()?;
+    on_progress(SetupProgress::StepStarted {
+        step: SetupStep::EnableService,
+    });
+
+    ssh_client
+        .run(&remote_os.service_manager.enable_command(&settings.interface))
+        .await?;
 
     save_state(ssh_client, &state).await?;
 
+    on_progress(SetupProgress::StepFinished {
+        step: SetupStep::EnableService,
+    });
+
+    if let Some(resolver) = settings.resolver {
+        ssh_client.ensure_not_cancelled()?;
+        on_progress(SetupProgress::StepStarted {
+            step: SetupStep::Resolver,
+        });
+
+        install_resolver(ssh_client, &remote_os, resolver, settings).await?;
+
+        on_progress(SetupProgress::StepFinished {
+            step: SetupStep::Resolver,
+        });
+    }
+
     Ok(SetupResult {
-        client_private_key: peer_priv_key,
+        client_private_key: peer_keys.private_key,
+        client_preshared_key: peer_keys.preshared_key,
         server_public_key: server_pub,
         client_ip: new_peer.ip,
-        public_ip,
+        client_ipv6: new_peer.ipv6,
+        host: host.clone(),
+        settings: settings.clone(),
+    })
+}
+
+pub async fn teardown_wireguard(ssh_client: &SshClient) -> anyhow::Result<()> {
+    let remote_os = detect_remote_os(ssh_client).await?;
+    // Servers from before peers.json always used the defaults.
+    let (settings, forwarding) = load_state(ssh_client)
+        .await?
+        .map(|state| (state.settings, state.forwarding))
+        .unwrap_or_default();
+    let interface = settings.interface.as_str();
+
+    ssh_client
+        .run(&RemoteCommand::new("wg-quick").args(["down", interface]))
+        .await?;
+    ssh_client
+        .run(&remote_os.service_manager.disable_command(interface))
+        .await?;
+
+    if let Some(host_firewall) = settings.host_firewall {
+        close_host_firewall(ssh_client, host_firewall, &settings).await?;
+    }
+
+    if let Some(resolver) = settings.resolver {
+        uninstall_resolver(ssh_client, &remote_os, resolver).await?;
+    }
+
+    ssh_client
+        .run(&RemoteCommand::new("rm").args([
+            "-f".to_string(),
+            settings.config_path().display().to_string(),
+            STATE_PATH.to_string(),
+        ]))
+        .await?;
+
+    // Another WireGuard interface may still need forwarding.
+    let (_, other_configs) = ssh_client
+        .run(&RemoteCommand::shell("ls /etc/wireguard/*.conf"))
+        .await?;
+    if other_configs != 0 {
+        for key in &forwarding.persisted {
+            ssh_client
+                .run(&remove_line_command("/etc/sysctl.conf", &format!("{}=1", key)))
+                .await?;
+        }
+        for key in &forwarding.enabled {
+            ssh_client
+                .run(&RemoteCommand::new("sysctl").args(["-w".to_string(), format!("{}=0", key)]))
+                .await?;
+        }
+    }
+
+    // Only removed when nothing else lives there.
+    ssh_client
+        .run(&RemoteCommand::shell("rmdir /etc/wireguard 2>/dev/null || true"))
+        .await?;
+
+    anyhow::Ok(())
+}
+
+async fn join_installation(
+    ssh_client: &SshClient,
+    host: &Host,
+    mut state: VpnState,
+    on_progress: ProgressReporter<'_>,
+) -> anyhow::Result<SetupResult> {
+    on_progress(SetupProgress::StepFinished {
+        step: SetupStep::Install,
+    });
+    ssh_client.ensure_not_cancelled()?;
+    on_progress(SetupProgress::StepStarted {
+        step: SetupStep::Keygen,
+    });
+
+    let name = format!("client-{}", state.get_next_available_ip()?);
+    let (new_peer, peer_keys) = add_peer(ssh_client, &mut state, name).await?;
+
+    on_progress(SetupProgress::StepFinished {
+        step: SetupStep::Keygen,
+    });
+
+    Ok(SetupResult {
+        client_private_key: peer_keys.private_key,
+        client_preshared_key: peer_keys.preshared_key,
+        server_public_key: state.server_public_key,
+        client_ip: new_peer.ip,
+        client_ipv6: new_peer.ipv6,
+        host: host.clone(),
+        settings: state.settings,
     })
 }
 
@@ -186,33 +820,47 @@ pub async fn update_wireguard_config(
     ssh_client: &SshClient,
     state: &VpnState,
 ) -> anyhow::Result<()> {
-    let (current_peers_raw, _) = ssh_client.exec("wg show wg0 peers").await?;
+    let interface = state.settings.interface.as_str();
+
+    let (current_peers_raw, _) = ssh_client
+        .run(&RemoteCommand::new("wg").args(["show", interface, "peers"]))
+        .await?;
     let active_keys: Vec<&str> = current_peers_raw.lines().collect();
 
     for key in active_keys {
         if !key.is_empty() && !state.peers.iter().any(|p| p.public_key == key) {
             ssh_client
-                .exec(&format!("wg set wg0 peer {} remove", key))
+                .run(&RemoteCommand::new("wg").args(["set", interface, "peer", key, "remove"]))
                 .await?;
         }
     }
 
     for peer in &state.peers {
-        ssh_client
-            .exec(&format!(
-                "wg set wg0 peer {} allowed-ips {}/32",
-                peer.public_key, peer.ip
-            ))
-            .await?;
+        let set_peer = RemoteCommand::new("wg").args([
+            "set".to_string(),
+            interface.to_string(),
+            "peer".to_string(),
+            peer.public_key.clone(),
+            "allowed-ips".to_string(),
+            peer.allowed_ips(),
+        ]);
+        ssh_client.run(&set_peer).await?;
     }
 
-    ssh_client.exec("wg-quick save wg0").await?;
+    ssh_client
+        .run(&RemoteCommand::new("wg-quick").args(["save", interface]))
+        .await?;
 
     anyhow::Ok(())
 }
 
-pub async fn get_server_public_key(ssh_client: &SshClient) -> anyhow::Result<String> {
-    let (pub_key, status) = ssh_client.exec("wg show wg0 public-key").await?;
+pub async fn get_server_public_key(
+    ssh_client: &SshClient,
+    interface: &str,
+) -> anyhow::Result<String> {
+    let (pub_key, status) = ssh_client
+        .run(&RemoteCommand::new("wg").args(["show", interface, "public-key"]))
+        .await?;
 
     if status != 0 {
         return Err(ServerError::CommandFailed {
@@ -0,0 +1,208 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ops::RangeInclusive,
+    path::PathBuf,
+};
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::wireguard::{
+    firewall::{FirewallBackend, HostFirewall},
+    resolver::DnsResolver,
+};
+
+pub const DEFAULT_DNS: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error("Subnet {0} is too small, use a /30 or larger")]
+    SubnetTooSmall(Ipv4Net),
+    #[error("IPv6 subnet {0} is too small to mirror every address of {1}")]
+    SubnetV6TooSmall(Ipv6Net, Ipv4Net),
+    #[error("Listen port cannot be 0")]
+    InvalidPort,
+    #[error("Invalid interface name '{0}': use up to 15 letters, digits, '_', '.', '=', '+' or '-'")]
+    InvalidInterface(String),
+    #[error("MTU {0} is out of range (1280-9000)")]
+    InvalidMtu(u16),
+    #[error("Keepalive interval must be at least 1 second, leave it empty to disable it")]
+    InvalidKeepalive,
+}
+
+/// Defaults match what older versions hard-coded, so older state files still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub subnet: Ipv4Net,
+    /// Peers get the address with the same host part as their IPv4 one.
+    pub subnet_v6: Option<Ipv6Net>,
+    pub listen_port: u16,
+    pub interface: String,
+    pub mtu: Option<u16>,
+    /// `None` uses the interface of the default route.
+    pub egress_interface: Option<String>,
+    pub firewall: Option<FirewallBackend>,
+    pub resolver: Option<DnsResolver>,
+    pub preshared_keys: bool,
+    /// Kept so teardown removes the rules setup added.
+    pub host_firewall: Option<HostFirewall>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            subnet: Ipv4Net::new(Ipv4Addr::new(10, 0, 0, 0), 24).unwrap(),
+            subnet_v6: None,
+            listen_port: 51820,
+            interface: "wg0".to_string(),
+            mtu: None,
+            egress_interface: None,
+            firewall: None,
+            resolver: None,
+            preshared_keys: false,
+            host_firewall: None,
+        }
+    }
+}
+
+impl ServerSettings {
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.subnet.prefix_len() > 30 {
+            return Err(SettingsError::SubnetTooSmall(self.subnet));
+        }
+
+        if let Some(subnet_v6) = self.subnet_v6 {
+            let v4_host_bits = 32 - self.subnet.prefix_len();
+            let v6_host_bits = 128 - subnet_v6.prefix_len();
+
+            if v6_host_bits < v4_host_bits {
+                return Err(SettingsError::SubnetV6TooSmall(subnet_v6, self.subnet));
+            }
+        }
+
+        if self.listen_port == 0 {
+            return Err(SettingsError::InvalidPort);
+        }
+
+        if !is_valid_interface_name(&self.interface) {
+            return Err(SettingsError::InvalidInterface(self.interface.clone()));
+        }
+
+        if let Some(egress) = &self.egress_interface
+            && !is_valid_interface_name(egress)
+        {
+            return Err(SettingsError::InvalidInterface(egress.clone()));
+        }
+
+        validate_mtu(self.mtu)
+    }
+
+    pub fn server_address(&self) -> Ipv4Addr {
+        self.subnet.hosts().next().unwrap_or(self.subnet.addr())
+    }
+
+    pub fn ipv6_for(&self, ip: Ipv4Addr) -> Option<Ipv6Addr> {
+        let subnet_v6 = self.subnet_v6?;
+        let host_part = u32::from(ip) - u32::from(self.subnet.network());
+
+        Some(Ipv6Addr::from(
+            u128::from(subnet_v6.network()) + host_part as u128,
+        ))
+    }
+
+    pub fn server_address_v6(&self) -> Option<Ipv6Addr> {
+        self.ipv6_for(self.server_address())
+    }
+
+    pub fn interface_addresses(&self, ip: Ipv4Addr, ipv6: Option<Ipv6Addr>) -> Vec<IpNet> {
+        let ipv4 = Ipv4Net::new(ip, self.subnet.prefix_len()).ok().map(IpNet::V4);
+        let ipv6 = self
+            .subnet_v6
+            .zip(ipv6)
+            .and_then(|(subnet_v6, ipv6)| Ipv6Net::new(ipv6, subnet_v6.prefix_len()).ok())
+            .map(IpNet::V6);
+
+        ipv4.into_iter().chain(ipv6).collect()
+    }
+
+    pub fn client_dns(&self) -> Vec<IpAddr> {
+        if self.resolver.is_none() {
+            return vec![DEFAULT_DNS];
+        }
+
+        std::iter::once(IpAddr::V4(self.server_address()))
+            .chain(self.server_address_v6().map(IpAddr::V6))
+            .collect()
+    }
+
+    pub fn client_settings(&self) -> ClientSettings {
+        ClientSettings {
+            dns: self.client_dns(),
+            ..ClientSettings::default()
+        }
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        PathBuf::from(format!("/etc/wireguard/{}.conf", self.interface))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientSettings {
+    /// Empty leaves the system's DNS alone.
+    pub dns: Vec<IpAddr>,
+    pub dns_search: Vec<String>,
+    pub mtu: Option<u16>,
+    pub persistent_keepalive: Option<u16>,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            dns: vec![DEFAULT_DNS],
+            dns_search: Vec::new(),
+            mtu: None,
+            persistent_keepalive: None,
+        }
+    }
+}
+
+impl ClientSettings {
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        validate_mtu(self.mtu)?;
+
+        if self.persistent_keepalive == Some(0) {
+            return Err(SettingsError::InvalidKeepalive);
+        }
+
+        Ok(())
+    }
+}
+
+const MTU_RANGE: RangeInclusive<u16> = 1280..=9000;
+
+fn validate_mtu(mtu: Option<u16>) -> Result<(), SettingsError> {
+    match mtu {
+        Some(mtu) if !MTU_RANGE.contains(&mtu) => Err(SettingsError::InvalidMtu(mtu)),
+        _ => Ok(()),
+    }
+}
+
+pub fn random_ula_subnet() -> Ipv6Net {
+    let mut octets = [0u8; 16];
+    octets[0] = 0xfd;
+    OsRng.fill_bytes(&mut octets[1..6]);
+
+    Ipv6Net::new(Ipv6Addr::from(octets), 64).unwrap()
+}
+
+pub fn is_valid_interface_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 15
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.=+-".contains(c))
+}
@@ -1,102 +1,158 @@
 use chrono::{DateTime, Utc};
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
-use std::net::Ipv4Addr;
+use std::{net::Ipv4Addr, path::Path};
 
 use crate::{
-    ssh::{SshClient, SshSession, run_remote_cmd},
-    wireguard::{peer::Peer, server},
+    host::Host,
+    ssh::{RemoteCommand, SshClient, SshSession, run_remote_cmd},
+    wireguard::{peer::Peer, server, settings::ServerSettings},
 };
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct VpnState {
     pub server_public_key: String,
-    pub server_ip: Ipv4Addr,
+    /// Kept as entered so `Endpoint` follows DNS changes.
+    #[serde(alias = "server_ip")]
+    pub server_host: Host,
+    #[serde(default)]
+    pub settings: ServerSettings,
     pub peers: Vec<Peer>,
     pub last_updated: DateTime<Utc>,
+    #[serde(default)]
+    pub forwarding: ForwardingChanges,
+}
+
+/// Forwarding sysctls setup switched on, so teardown reverts only those.
+#[derive(Deserialize, Debug, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ForwardingChanges {
+    /// Keys whose `key=1` line setup appended to /etc/sysctl.conf.
+    pub persisted: Vec<String>,
+    /// Keys that were off at runtime until setup enabled them.
+    pub enabled: Vec<String>,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum StateError {
-    #[error("The network is full (maximum 253 peers reached)")]
-    NetworkFull,
+    #[error("The network {0} is full, no addresses left for new peers")]
+    NetworkFull(ipnet::Ipv4Net),
+    #[error("No peer with public key {0} exists on the server")]
+    PeerNotFound(String),
 }
 
 impl VpnState {
     fn default() -> Self {
         Self {
             server_public_key: String::new(),
-            server_ip: Ipv4Addr::new(0, 0, 0, 0),
+            server_host: Host::Ip(Ipv4Addr::UNSPECIFIED.into()),
+            settings: ServerSettings::default(),
             peers: Vec::new(),
             last_updated: Utc::now(),
+            forwarding: ForwardingChanges::default(),
         }
     }
 
-    pub fn new(server_public_key: String, server_ip: Ipv4Addr) -> Self {
+    pub fn new(server_public_key: String, server_host: Host, settings: ServerSettings) -> Self {
         Self {
             server_public_key,
-            server_ip,
+            server_host,
+            settings,
             peers: Vec::new(),
             last_updated: Utc::now(),
+            forwarding: ForwardingChanges::default(),
         }
     }
 
     pub fn get_next_available_ip(&self) -> Result<Ipv4Addr, StateError> {
-        let base_ip = [10, 0, 0, 0];
+        let server_address = self.settings.server_address();
+
+        self.settings
+            .subnet
+            .hosts()
+            .find(|ip| *ip != server_address && !self.peers.iter().any(|p| p.ip == *ip))
+            .ok_or(StateError::NetworkFull(self.settings.subnet))
+    }
+}
 
-        let max_octet = self
-            .peers
-            .iter()
-            .map(|p| p.ip.octets()[3])
-            .max()
-            .unwrap_or(1);
+pub const STATE_PATH: &str = "/etc/wireguard/peers.json";
 
-        if max_octet >= 254 {
-            return Err(StateError::NetworkFull);
-        }
+pub async fn load_state(ssh_client: &SshClient) -> anyhow::Result<Option<VpnState>> {
+    let cmd = RemoteCommand::new("cat").arg(STATE_PATH);
 
-        Ok(Ipv4Addr::new(
-            base_ip[0],
-            base_ip[1],
-            base_ip[2],
-            max_octet + 1,
-        ))
+    let (output, status) = ssh_client.run(&cmd).await?;
+
+    if status != 0 || output.trim().is_empty() {
+        return Ok(None);
     }
+
+    Ok(Some(serde_json::from_str(&output)?))
 }
 
 pub async fn get_or_create_state(
     ssh_client: &SshClient,
-    server_ip: Ipv4Addr,
+    server_host: &Host,
 ) -> anyhow::Result<VpnState> {
-    let cmd = "cat /etc/wireguard/peers.json";
-
-    let (output, status) = ssh_client.exec_raw(&cmd).await?;
-
-    if status != 0 || output.trim().is_empty() {
-        let server_pub = server::get_server_public_key(ssh_client).await?;
-        Ok(VpnState::new(server_pub, server_ip))
-    } else {
-        Ok(serde_json::from_str(&output)?)
+    if let Some(state) = load_state(ssh_client).await? {
+        return Ok(state);
     }
-}
 
-pub async fn save_state(ssh_client: &SshClient, state: &VpnState) -> anyhow::Result<()> {
-    let json = serde_json::to_string_pretty(state)?;
-    let escaped_json = json.replace("'", "'\\''");
+    // No state file means a server set up before peers.json existed, which
+    // always used the default settings. Its peers are taken from the running
+    // interface, otherwise the next config sync would remove them.
+    let settings = ServerSettings::default();
+    let server_pub = server::get_server_public_key(ssh_client, &settings.interface).await?;
+
+    let mut state = VpnState::new(server_pub, server_host.clone(), settings);
+    state.peers = import_live_peers(ssh_client, &state.settings.interface).await?;
 
-    let cmd = format!(
-        "echo '{}' | {} tee /etc/wireguard/peers.json > /dev/null",
-        escaped_json, ssh_client.sudo_prefix
-    );
+    Ok(state)
+}
 
-    let (output, status) = ssh_client.exec_raw(&cmd).await?;
+async fn import_live_peers(ssh_client: &SshClient, interface: &str) -> anyhow::Result<Vec<Peer>> {
+    let (output, status) = ssh_client
+        .run(&RemoteCommand::new("wg").args(["show", interface, "allowed-ips"]))
+        .await?;
 
     if status != 0 {
-        return Err(anyhow::anyhow!(
-            "Failed to save state to server. Exit code: {}. Error: {}",
-            status,
-            output
-        ));
+        return Ok(Vec::new());
     }
 
-    anyhow::Ok(())
+    let peers = output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let public_key = fields.next()?;
+            let nets: Vec<IpNet> = fields.filter_map(|field| field.parse().ok()).collect();
+
+            let ip = nets.iter().find_map(|net| match net {
+                IpNet::V4(net) => Some(net.addr()),
+                IpNet::V6(_) => None,
+            })?;
+            let ipv6 = nets.iter().find_map(|net| match net {
+                IpNet::V6(net) => Some(net.addr()),
+                IpNet::V4(_) => None,
+            });
+
+            Some(Peer {
+                name: format!("peer-{}", ip),
+                public_key: public_key.to_string(),
+                ip,
+                ipv6,
+                has_preshared_key: false,
+                crated_at: Utc::now(),
+            })
+        })
+        .collect();
+
+    Ok(peers)
+}
+
+pub async fn save_state(ssh_client: &SshClient, state: &VpnState) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(state)?;
+
+    ssh_client
+        .upload(Path::new(STATE_PATH), json.as_bytes(), 0o600, None)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to save state to server: {}", e))
 }
@@ -0,0 +1,241 @@
+use std::net::SocketAddr;
+
+use chrono::{DateTime, Duration, Utc};
+use ipnet::IpNet;
+use serde::Serialize;
+
+use crate::{
+    host::Host,
+    ssh::{RemoteCommand, SshClient},
+    wireguard::{peer::Peer, state::get_or_create_state},
+};
+
+/// WireGuard re-handshakes every two minutes on an active tunnel.
+const ONLINE_WINDOW: Duration = Duration::minutes(3);
+
+#[derive(Debug, thiserror::Error)]
+pub enum StatsError {
+    #[error("Failed to read WireGuard statistics for {interface}: {message}")]
+    Command { interface: String, message: String },
+    #[error("Unexpected line in `wg show dump` output: {0}")]
+    InvalidDump(String),
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PeerStats {
+    pub name: Option<String>,
+    pub public_key: String,
+    pub endpoint: Option<SocketAddr>,
+    pub allowed_ips: Vec<IpNet>,
+    pub latest_handshake: Option<DateTime<Utc>>,
+    pub online: bool,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub persistent_keepalive: Option<u16>,
+}
+
+pub async fn peer_stats(ssh_client: &SshClient, host: &Host) -> anyhow::Result<Vec<PeerStats>> {
+    let state = get_or_create_state(ssh_client, host).await?;
+    let interface = &state.settings.interface;
+
+    let (output, status) = ssh_client
+        .run(&RemoteCommand::new("wg").args(["show", interface.as_str(), "dump"]))
+        .await?;
+    if status != 0 {
+        return Err(StatsError::Command {
+            interface: interface.clone(),
+            message: output.trim().to_string(),
+        }
+        .into());
+    }
+
+    Ok(parse_dump(&output, &state.peers, Utc::now())?)
+}
+
+pub fn parse_dump(
+    output: &str,
+    peers: &[Peer],
+    now: DateTime<Utc>,
+) -> Result<Vec<PeerStats>, StatsError> {
+    output
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_peer_line(line, peers, now))
+        .collect()
+}
+
+fn parse_peer_line(
+    line: &str,
+    peers: &[Peer],
+    now: DateTime<Utc>,
+) -> Result<PeerStats, StatsError> {
+    let invalid = || StatsError::InvalidDump(line.to_string());
+
+    let fields: Vec<&str> = line.split('\t').collect();
+    let [
+        public_key,
+        _preshared_key,
+        endpoint,
+        allowed_ips,
+        latest_handshake,
+        rx_bytes,
+        tx_bytes,
+        persistent_keepalive,
+    ] = fields.as_slice()
+    else {
+        return Err(invalid());
+    };
+
+    let endpoint = match *endpoint {
+        "(none)" => None,
+        endpoint => Some(endpoint.parse().map_err(|_| invalid())?),
+    };
+    let allowed_ips = match *allowed_ips {
+        "(none)" => Vec::new(),
+        allowed_ips => allowed_ips
+            .split(',')
+            .map(|net| net.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?,
+    };
+    let latest_handshake = match latest_handshake.parse::<i64>().map_err(|_| invalid())? {
+        0 => None,
+        seconds => Some(DateTime::from_timestamp(seconds, 0).ok_or_else(invalid)?),
+    };
+    let persistent_keepalive = match *persistent_keepalive {
+        "off" => None,
+        seconds => Some(seconds.parse().map_err(|_| invalid())?),
+    };
+
+    Ok(PeerStats {
+        name: peers
+            .iter()
+            .find(|peer| peer.public_key == *public_key)
+            .map(|peer| peer.name.clone()),
+        public_key: public_key.to_string(),
+        endpoint,
+        allowed_ips,
+        online: latest_handshake.is_some_and(|handshake| now - handshake < ONLINE_WINDOW),
+        latest_handshake,
+        rx_bytes: rx_bytes.parse().map_err(|_| invalid())?,
+        tx_bytes: tx_bytes.parse().map_err(|_| invalid())?,
+        persistent_keepalive,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAPTOP_KEY: &str = "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=";
+    const PHONE_KEY: &str = "TrMvSoP4jYQlY6RIzBgbssQqY3vxI2Pi+y71lOWWXX0=";
+    const UNKNOWN_KEY: &str = "gN65BkIKy1eCE9pP1wdc8ROUtkHLF2PfAqYdyYBz6EA=";
+
+    const NOW: i64 = 1_760_000_000;
+
+    fn dump(handshakes: [i64; 3]) -> String {
+        format!(
+            "yAnz5TF+lXXJte14tji3zlMNq+hd2rYUIgJBgB3fBmk=\tHIgo9xNzJMWLKASShiTqIybxZ0U3wGLiUeJ1PKf8ykw=\t51820\toff
+{LAPTOP_KEY}\t(none)\t203.0.113.7:41234\t10.0.0.2/32,fd00::2/128\t{}\t1048576\t524288\t25
+{PHONE_KEY}\tFpCyhws9cxwWoV4xELtfJvjJN+zQVRPISllRWgeopVE=\t(none)\t10.0.0.3/32\t{}\t0\t0\toff
+{UNKNOWN_KEY}\t(none)\t[2001:db8::9]:51820\t(none)\t{}\t92\t148\toff
+",
+            handshakes[0], handshakes[1], handshakes[2]
+        )
+    }
+
+    fn peers() -> Vec<Peer> {
+        [("laptop", LAPTOP_KEY, 2), ("phone", PHONE_KEY, 3)]
+            .into_iter()
+            .map(|(name, public_key, host)| Peer {
+                name: name.to_string(),
+                public_key: public_key.to_string(),
+                ip: [10, 0, 0, host].into(),
+                ipv6: None,
+                has_preshared_key: false,
+                crated_at: DateTime::from_timestamp(0, 0).unwrap(),
+            })
+            .collect()
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_timestamp(NOW, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_every_peer_and_skips_the_interface_line() {
+        let stats = parse_dump(&dump([NOW - 30, 0, NOW - 600]), &peers(), now()).unwrap();
+
+        let keys: Vec<&str> = stats.iter().map(|peer| peer.public_key.as_str()).collect();
+        assert_eq!(keys, [LAPTOP_KEY, PHONE_KEY, UNKNOWN_KEY]);
+
+        let laptop = &stats[0];
+        assert_eq!(laptop.name.as_deref(), Some("laptop"));
+        assert_eq!(laptop.endpoint, Some("203.0.113.7:41234".parse().unwrap()));
+        assert_eq!(
+            laptop.allowed_ips,
+            [
+                "10.0.0.2/32".parse::<IpNet>().unwrap(),
+                "fd00::2/128".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            laptop.latest_handshake,
+            DateTime::from_timestamp(NOW - 30, 0)
+        );
+        assert_eq!((laptop.rx_bytes, laptop.tx_bytes), (1_048_576, 524_288));
+        assert_eq!(laptop.persistent_keepalive, Some(25));
+
+        assert_eq!(stats[2].name, None);
+        assert_eq!(
+            stats[2].endpoint,
+            Some("[2001:db8::9]:51820".parse().unwrap())
+        );
+        assert!(stats[2].allowed_ips.is_empty());
+    }
+
+    #[test]
+    fn peer_that_never_connected_has_no_endpoint_or_handshake() {
+        let stats = parse_dump(&dump([NOW, 0, NOW]), &peers(), now()).unwrap();
+        let phone = &stats[1];
+
+        assert_eq!(phone.name.as_deref(), Some("phone"));
+        assert_eq!(phone.endpoint, None);
+        assert_eq!(phone.latest_handshake, None);
+        assert_eq!(phone.persistent_keepalive, None);
+        assert!(!phone.online);
+    }
+
+    #[test]
+    fn online_only_within_the_handshake_window() {
+        let window = ONLINE_WINDOW.num_seconds();
+        let stats =
+            parse_dump(&dump([NOW - window + 1, 0, NOW - window]), &peers(), now()).unwrap();
+
+        assert!(stats[0].online);
+        assert!(!stats[2].online);
+    }
+
+    #[test]
+    fn empty_interface_has_no_peers() {
+        let output = "yAnz5TF+lXXJte14tji3zlMNq+hd2rYUIgJBgB3fBmk=\tHIgo9xNzJMWLKASShiTqIybxZ0U3wGLiUeJ1PKf8ykw=\t51820\toff\n";
+
+        assert!(parse_dump(output, &peers(), now()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let interface = "private\tpublic\t51820\toff\n";
+
+        for line in [
+            format!("{LAPTOP_KEY}\t(none)\t(none)"),
+            format!("{LAPTOP_KEY}\t(none)\tnot-an-endpoint\t(none)\t0\t0\t0\toff"),
+            format!("{LAPTOP_KEY}\t(none)\t(none)\t(none)\tyesterday\t0\t0\toff"),
+        ] {
+            assert!(matches!(
+                parse_dump(&format!("{interface}{line}"), &peers(), now()),
+                Err(StatsError::InvalidDump(invalid)) if invalid == line
+            ));
+        }
+    }
+}